@@ -1,3 +1,5 @@
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::time::Duration;
 
 use poise::builtins;
@@ -10,7 +12,7 @@ async fn main() {
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
             allowed_mentions: None,
-            commands: vec![lex(), ast(), exec(), ri()],
+            commands: vec![lex(), ast(), exec(), test(), ri()],
 
             prefix_options: poise::PrefixFrameworkOptions {
                 prefix: Some("~".to_owned()),
@@ -45,6 +47,17 @@ pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub type Context<'a> = poise::Context<'a, Data, Error>;
 pub type Result = std::result::Result<(), Error>;
 
+/// How far code run from a message may go before it is stopped.
+const LIMITS: vea::limits::Limits = vea::limits::Limits {
+    steps: Some(10_000_000),
+    depth: Some(200),
+    size: Some(1 << 20),
+    allocations: Some(5_000_000),
+};
+
+/// How long code run from a message may take before it is cancelled.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
 #[poise::command(prefix_command)]
 async fn lex(context: Context<'_>, esrc: Option<CodeBlock>) -> Result {
     let src = if let Some(e) = esrc {
@@ -109,10 +122,19 @@ fn v_ast(c: &str) -> String {
     m
 }
 
+/// Runs a code block. Any text after it is the program's input.
 #[poise::command(prefix_command)]
-async fn exec(context: Context<'_>, esrc: Option<CodeBlock>) -> Result {
+async fn exec(
+    context: Context<'_>,
+    esrc: Option<CodeBlock>,
+    #[rest] input: Option<String>,
+) -> Result {
     if let Some(e) = esrc {
-        let x = v_exec(&e.code);
+        let interrupt = vea::interrupt::Interrupt::with_timeout(TIMEOUT);
+        let input = input.unwrap_or_default();
+
+        // off of the async runtime, as it runs until it is done or cancelled
+        let x = tokio::task::spawn_blocking(move || v_exec(&e.code, &input, interrupt)).await?;
         context.say(x).await?;
     } else {
         context.say("code?".to_owned()).await?;
@@ -122,7 +144,7 @@ async fn exec(context: Context<'_>, esrc: Option<CodeBlock>) -> Result {
     Ok(())
 }
 
-fn v_exec(c: &str) -> String {
+fn v_exec(c: &str, input: &str, interrupt: vea::interrupt::Interrupt) -> String {
     let mut z = String::new();
     let l = vea::lex(c);
 
@@ -138,7 +160,15 @@ fn v_exec(c: &str) -> String {
         }
 
         if let Some(p) = x.0 {
-            let m = vea::interp(c, &t, p);
+            let env = vea::new_env();
+            env.borrow_mut().input = Rc::new(RefCell::new(vea::input::Text::new(input.trim())));
+            env.borrow_mut().backend = vea::vm::Backend::Vm;
+            env.borrow_mut().meter = Rc::new(vea::limits::Meter::new(LIMITS));
+            env.borrow_mut().interrupt = interrupt;
+
+            let m = vea::interp_in(c, &t, p, &env);
+            vea::gc::free(env);
+
             if !m.is_empty() {
                 z += &format!("```ansi\n{m}\n```");
             }
@@ -148,6 +178,51 @@ fn v_exec(c: &str) -> String {
     z
 }
 
+#[poise::command(prefix_command)]
+async fn test(context: Context<'_>, esrc: Option<CodeBlock>) -> Result {
+    if let Some(e) = esrc {
+        let interrupt = vea::interrupt::Interrupt::with_timeout(TIMEOUT);
+        let x = tokio::task::spawn_blocking(move || v_test(&e.code, interrupt)).await?;
+        context.say(x).await?;
+    } else {
+        context.say("code?".to_owned()).await?;
+    };
+
+    Ok(())
+}
+
+fn v_test(c: &str, interrupt: vea::interrupt::Interrupt) -> String {
+    let mut z = String::new();
+    let l = vea::lex(c);
+
+    if !l.1.is_empty() {
+        z += &format!("lexing errors: ```ansi\n{}\n```", l.1);
+    }
+
+    if let Some(t) = l.0 {
+        let x = vea::parse(c, &t);
+
+        if !x.1.is_empty() {
+            z += &format!("parsing errors: ```ansi\n{}\n```", x.1);
+        }
+
+        if let Some(p) = x.0 {
+            // every test gets its own limits, but they share the timeout
+            let new_env = || {
+                let env = vea::new_env();
+                env.borrow_mut().backend = vea::vm::Backend::Vm;
+                env.borrow_mut().meter = Rc::new(vea::limits::Meter::new(LIMITS));
+                env.borrow_mut().interrupt = interrupt.clone();
+                env
+            };
+
+            z += &format!("```ansi\n{}\n```", vea::test_in(c, &t, &p, new_env));
+        }
+    }
+
+    z
+}
+
 fn v_ri(c: &str) -> String {
     let mut m = String::new();
     let l = vea::lex(c);
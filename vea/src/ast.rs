@@ -2,45 +2,80 @@ use crate::common::VeaErr;
 use crate::lexer::Token;
 use crate::literal::Literal;
 use crate::span::Span;
+use crate::symbol::Symbol;
+
+/// A hole of an interpolated string: its expression, format spec and the text after it.
+pub type Hole = (Span<Expr>, Option<Span<Token>>, Span<Token>);
+
+/// A value written out in the source, like `1`, `'a'` or `true`. Unlike a [`Literal`], it refers to
+/// nothing else, so a parsed program can be sent to another thread.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Constant {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+}
+
+impl From<Constant> for Literal<'_> {
+    fn from(value: Constant) -> Self {
+        match value {
+            Constant::Bool(x) => Self::Bool(x),
+            Constant::Integer(x) => Self::Integer(x),
+            Constant::String(x) => Self::String(x),
+        }
+    }
+}
 
 #[derive(Default, Clone, Debug)]
-pub enum Expr<'a> {
+pub enum Expr {
     Access {
-        ident: Span<&'a str>,
+        ident: Span<Symbol>,
     },
 
     Print {
-        left_paren: Span<Token<'a>>,
-        print_token: Span<Token<'a>>,
-        right_paren: Span<Token<'a>>,
-        semi_token: Span<Token<'a>>,
+        left_paren: Span<Token>,
+        print_token: Span<Token>,
+        right_paren: Span<Token>,
+        semi_token: Span<Token>,
 
-        value: Box<Span<Self>>,
+        values: Vec<Span<Self>>,
+    },
+
+    /// `'a {b} c {d:x} e'`
+    Interp {
+        start: Span<Token>,
+        holes: Vec<Hole>,
     },
 
     Return {
-        return_token: Span<Token<'a>>,
-        semi_token: Span<Token<'a>>,
+        return_token: Span<Token>,
+        semi_token: Span<Token>,
 
         value: Box<Span<Self>>,
     },
 
     Literal {
-        value: Literal<'a>,
+        value: Constant,
     },
 
     Object {
-        struct_token: Span<Token<'a>>,
-        left_brace: Span<Token<'a>>,
+        struct_token: Span<Token>,
+        left_brace: Span<Token>,
         exprs: Vec<Span<Self>>,
-        right_brace: Span<Token<'a>>,
+        right_brace: Span<Token>,
     },
 
     Set {
-        set_token: Span<Token<'a>>,
-        left_brace: Span<Token<'a>>,
+        set_token: Span<Token>,
+        left_brace: Span<Token>,
         exprs: Vec<Span<Self>>,
-        right_brace: Span<Token<'a>>,
+        right_brace: Span<Token>,
+    },
+
+    Array {
+        left_bracket: Span<Token>,
+        exprs: Vec<Span<Self>>,
+        right_bracket: Span<Token>,
     },
 
     Chain {
@@ -48,241 +83,413 @@ pub enum Expr<'a> {
         child: Box<Span<Self>>,
     },
 
+    // `x[a..b]`
+    Slice {
+        parent: Box<Span<Self>>,
+        start: Option<Box<Span<Self>>>,
+        end: Option<Box<Span<Self>>>,
+        inclusive: bool,
+    },
+
     Group {
-        left_paren: Span<Token<'a>>,
+        left_paren: Span<Token>,
         expr: Box<Span<Self>>,
-        right_paren: Span<Token<'a>>,
+        right_paren: Span<Token>,
     },
 
     Block {
-        left_brace: Span<Token<'a>>,
+        left_brace: Span<Token>,
         exprs: Vec<Span<Self>>,
-        right_brace: Span<Token<'a>>,
+        right_brace: Span<Token>,
     },
 
     If {
-        if_token: Span<Token<'a>>,
+        if_token: Span<Token>,
         condition: Box<Span<Self>>,
         then: Box<Span<Self>>,
-        else_token: Option<Span<Token<'a>>>,
+        else_token: Option<Span<Token>>,
         other: Option<Box<Span<Self>>>,
     },
 
     While {
-        while_token: Span<Token<'a>>,
+        while_token: Span<Token>,
         condition: Box<Span<Self>>,
         then: Box<Span<Self>>,
     },
 
     FnDecl {
-        fn_token: Span<Token<'a>>,
-        name: Span<&'a str>,
-        left_paren: Span<Token<'a>>,
-        arguments: Vec<Span<&'a str>>,
-        right_paren: Span<Token<'a>>,
+        test_token: Option<Span<Token>>,
+        unsafe_token: Option<Span<Token>>,
+        fn_token: Span<Token>,
+        name: Span<Symbol>,
+        left_paren: Span<Token>,
+        arguments: Vec<Span<Symbol>>,
+        right_paren: Span<Token>,
         block: Box<Span<Self>>,
     },
 
     FnCall {
         access: Box<Span<Self>>,
-        left_paren: Span<Token<'a>>,
+        left_paren: Span<Token>,
         arguments: Vec<Span<Self>>,
-        right_paren: Span<Token<'a>>,
+        right_paren: Span<Token>,
     },
 
     Let {
-        let_token: Span<Token<'a>>,
-        ident: Span<&'a str>,
-        eq_token: Span<Token<'a>>,
+        let_token: Span<Token>,
+        ident: Span<Symbol>,
+        eq_token: Span<Token>,
         expr: Box<Span<Self>>,
-        semi_token: Span<Token<'a>>,
+        semi_token: Span<Token>,
     },
 
     Assign {
-        ident: Span<&'a str>,
-        eq_token: Span<Token<'a>>,
+        ident: Span<Symbol>,
+        eq_token: Span<Token>,
         expr: Box<Span<Self>>,
-        semi_token: Span<Token<'a>>,
+        semi_token: Span<Token>,
     },
 
     AddAssign {
-        ident: Span<&'a str>,
-        plus_eq_token: Span<Token<'a>>,
+        ident: Span<Symbol>,
+        plus_eq_token: Span<Token>,
         expr: Box<Span<Self>>,
-        semi_token: Span<Token<'a>>,
+        semi_token: Span<Token>,
     },
 
     SubAssign {
-        ident: Span<&'a str>,
-        minus_eq_token: Span<Token<'a>>,
+        ident: Span<Symbol>,
+        minus_eq_token: Span<Token>,
         expr: Box<Span<Self>>,
-        semi_token: Span<Token<'a>>,
+        semi_token: Span<Token>,
     },
 
     MulAssign {
-        ident: Span<&'a str>,
-        star_eq_token: Span<Token<'a>>,
+        ident: Span<Symbol>,
+        star_eq_token: Span<Token>,
         expr: Box<Span<Self>>,
-        semi_token: Span<Token<'a>>,
+        semi_token: Span<Token>,
     },
 
     DivAssign {
-        ident: Span<&'a str>,
-        slash_eq_token: Span<Token<'a>>,
+        ident: Span<Symbol>,
+        slash_eq_token: Span<Token>,
         expr: Box<Span<Self>>,
-        semi_token: Span<Token<'a>>,
+        semi_token: Span<Token>,
     },
 
     RemAssign {
-        ident: Span<&'a str>,
-        percent_eq_token: Span<Token<'a>>,
+        ident: Span<Symbol>,
+        percent_eq_token: Span<Token>,
         expr: Box<Span<Self>>,
-        semi_token: Span<Token<'a>>,
+        semi_token: Span<Token>,
     },
 
     ShlAssign {
-        ident: Span<&'a str>,
-        shl_eq_token: Span<Token<'a>>,
+        ident: Span<Symbol>,
+        shl_eq_token: Span<Token>,
         expr: Box<Span<Self>>,
-        semi_token: Span<Token<'a>>,
+        semi_token: Span<Token>,
     },
 
     ShrAssign {
-        ident: Span<&'a str>,
-        shr_eq_token: Span<Token<'a>>,
+        ident: Span<Symbol>,
+        shr_eq_token: Span<Token>,
         expr: Box<Span<Self>>,
-        semi_token: Span<Token<'a>>,
+        semi_token: Span<Token>,
     },
 
     AndAssign {
-        ident: Span<&'a str>,
-        and_eq_token: Span<Token<'a>>,
+        ident: Span<Symbol>,
+        and_eq_token: Span<Token>,
         expr: Box<Span<Self>>,
-        semi_token: Span<Token<'a>>,
+        semi_token: Span<Token>,
     },
 
     OrAssign {
-        ident: Span<&'a str>,
-        pipe_eq_token: Span<Token<'a>>,
+        ident: Span<Symbol>,
+        pipe_eq_token: Span<Token>,
         expr: Box<Span<Self>>,
-        semi_token: Span<Token<'a>>,
+        semi_token: Span<Token>,
     },
 
     XorAssign {
-        ident: Span<&'a str>,
-        caret_eq_token: Span<Token<'a>>,
+        ident: Span<Symbol>,
+        caret_eq_token: Span<Token>,
+        expr: Box<Span<Self>>,
+        semi_token: Span<Token>,
+    },
+
+    IndexAssign {
+        target: Box<Span<Self>>,
+        eq_token: Span<Token>,
         expr: Box<Span<Self>>,
-        semi_token: Span<Token<'a>>,
+        semi_token: Span<Token>,
+    },
+
+    // `some(T) { ... }` or `self::some(T) { ... }`, a fn that belongs to `self`
+    Method {
+        unsafe_token: Option<Span<Token>>,
+        self_token: Option<Span<Token>>,
+        name: Span<Symbol>,
+        left_paren: Span<Token>,
+        arguments: Vec<Span<Symbol>>,
+        right_paren: Span<Token>,
+        block: Box<Span<Self>>,
+    },
+
+    // `let self::data = 1;`
+    LetSelf {
+        let_token: Span<Token>,
+        self_token: Span<Token>,
+        ident: Span<Symbol>,
+        eq_token: Span<Token>,
+        expr: Box<Span<Self>>,
+        semi_token: Span<Token>,
+    },
+
+    This {
+        self_token: Span<Token>,
+    },
+
+    Unsafe {
+        unsafe_token: Span<Token>,
+        block: Box<Span<Self>>,
+    },
+
+    // `_`, which is only allowed in `unsafe` code
+    Empty {
+        underscore_token: Span<Token>,
+    },
+
+    // an expression used as a statement, like `f(x);`
+    Statement {
+        expr: Box<Span<Self>>,
+        semi_token: Span<Token>,
+    },
+
+    Use {
+        use_token: Span<Token>,
+        path: Vec<Span<Symbol>>,
+        semi_token: Span<Token>,
+    },
+
+    Lock {
+        lock_token: Span<Token>,
+        target: Box<Span<Self>>,
+        semi_token: Span<Token>,
     },
 
     Not {
-        bang_token: Span<Token<'a>>,
+        bang_token: Span<Token>,
         expr: Box<Span<Self>>,
     },
 
     Neg {
-        minus_token: Span<Token<'a>>,
+        minus_token: Span<Token>,
+        expr: Box<Span<Self>>,
+    },
+
+    Copy {
+        and_token: Span<Token>,
         expr: Box<Span<Self>>,
     },
 
     Add {
         lhs: Box<Span<Self>>,
-        plus_token: Span<Token<'a>>,
+        plus_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Sub {
         lhs: Box<Span<Self>>,
-        minus_token: Span<Token<'a>>,
+        minus_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Mul {
         lhs: Box<Span<Self>>,
-        star_token: Span<Token<'a>>,
+        star_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Div {
         lhs: Box<Span<Self>>,
-        slash_token: Span<Token<'a>>,
+        slash_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Rem {
         lhs: Box<Span<Self>>,
-        percent_token: Span<Token<'a>>,
+        percent_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Shl {
         lhs: Box<Span<Self>>,
-        shl_token: Span<Token<'a>>,
+        shl_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Shr {
         lhs: Box<Span<Self>>,
-        shr_token: Span<Token<'a>>,
+        shr_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     And {
         lhs: Box<Span<Self>>,
-        and_token: Span<Token<'a>>,
+        and_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Or {
         lhs: Box<Span<Self>>,
-        pipe_token: Span<Token<'a>>,
+        pipe_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Xor {
         lhs: Box<Span<Self>>,
-        caret_token: Span<Token<'a>>,
+        caret_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Eq {
         lhs: Box<Span<Self>>,
-        eqeq_token: Span<Token<'a>>,
+        eqeq_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Ne {
         lhs: Box<Span<Self>>,
-        ne_token: Span<Token<'a>>,
+        ne_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Gt {
         lhs: Box<Span<Self>>,
-        gt_token: Span<Token<'a>>,
+        gt_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Ge {
         lhs: Box<Span<Self>>,
-        ge_token: Span<Token<'a>>,
+        ge_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Lt {
         lhs: Box<Span<Self>>,
-        lt_token: Span<Token<'a>>,
+        lt_token: Span<Token>,
         rhs: Box<Span<Self>>,
     },
 
     Le {
         lhs: Box<Span<Self>>,
-        le_token: Span<Token<'a>>,
+        le_token: Span<Token>,
+        rhs: Box<Span<Self>>,
+    },
+
+    AlmostGt {
+        lhs: Box<Span<Self>>,
+        gt_tilde_token: Span<Token>,
+        epsilon: Box<Span<Self>>,
+        rhs: Box<Span<Self>>,
+    },
+
+    AlmostGe {
+        lhs: Box<Span<Self>>,
+        ge_tilde_token: Span<Token>,
+        epsilon: Box<Span<Self>>,
+        rhs: Box<Span<Self>>,
+    },
+
+    AlmostLt {
+        lhs: Box<Span<Self>>,
+        lt_tilde_token: Span<Token>,
+        epsilon: Box<Span<Self>>,
+        rhs: Box<Span<Self>>,
+    },
+
+    AlmostLe {
+        lhs: Box<Span<Self>>,
+        le_tilde_token: Span<Token>,
+        epsilon: Box<Span<Self>>,
         rhs: Box<Span<Self>>,
     },
 
+    AlmostEq {
+        lhs: Box<Span<Self>>,
+        eqeq_tilde_token: Span<Token>,
+        epsilon: Box<Span<Self>>,
+        rhs: Box<Span<Self>>,
+    },
+
+    Restrict {
+        expr: Box<Span<Self>>,
+        left_brace: Span<Token>,
+        pred: Box<Span<Self>>,
+        right_brace: Span<Token>,
+    },
+
+    Is {
+        lhs: Box<Span<Self>>,
+        question_token: Span<Token>,
+        pattern: Box<Span<Pattern>>,
+    },
+
     Error(VeaErr),
 
     #[default]
     None,
 }
+
+/// The right-hand side of `x?y`.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    // _
+    Wildcard,
+
+    // 1, 'a', true
+    Literal {
+        value: Constant,
+    },
+
+    // 0..10, 0..=10
+    Range {
+        start: i64,
+        end: i64,
+        inclusive: bool,
+    },
+
+    // a type name like `int`, otherwise the value of a variable
+    Ident {
+        ident: Span<Symbol>,
+    },
+
+    // (x + 1)
+    Value {
+        expr: Box<Span<Expr>>,
+    },
+
+    // struct { a: int, b }
+    Object {
+        fields: Vec<(Span<Symbol>, Span<Self>)>,
+    },
+
+    // set { 1, str }
+    Set {
+        items: Vec<Span<Self>>,
+    },
+
+    // [1, _, str]
+    Array {
+        items: Vec<Span<Self>>,
+    },
+
+    // int | str
+    Or {
+        alternatives: Vec<Span<Self>>,
+    },
+}
@@ -36,6 +36,16 @@ pub trait Tag {
 
 impl<T> Tag for T {}
 
+/// How much stack is left before [`grow`] switches to a new one, and how big that one is.
+const RED_ZONE: usize = 1024 * 1024;
+const STACK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Runs `f` on a new stack if this one is close to running out, so that whatever recurses on how
+/// deep a program nests, like evaluating it or a chain of calls, never overflows the stack.
+pub fn grow<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, STACK_SIZE, f)
+}
+
 #[macro_export]
 macro_rules! choice {
     ($start:expr, $($rest:expr),*$(,)?) => {{
@@ -0,0 +1,569 @@
+//! Compiles statements into bytecode for the [`vm`](crate::vm).
+//!
+//! Loops, arithmetic, calls and the like become flat instructions. Anything rarer, like a
+//! declaration or a pattern, stays a tree that the VM hands back to [`interp`](crate::interpreter::interp).
+
+use crate::{
+    ast::Expr,
+    common::grow,
+    interpreter::text,
+    lexer::Token,
+    literal::Literal,
+    resolver::{Resolution, Slot},
+    span::{RawSpan, Span},
+    symbol::Symbol,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+}
+
+impl BinOp {
+    pub fn apply<'a>(self, lhs: Literal<'a>, rhs: Literal<'a>) -> Result<Literal<'a>, String> {
+        match self {
+            Self::Add => lhs + rhs,
+            Self::Sub => lhs - rhs,
+            Self::Mul => lhs * rhs,
+            Self::Div => lhs / rhs,
+            Self::Rem => lhs % rhs,
+            Self::And => lhs & rhs,
+            Self::Or => lhs | rhs,
+            Self::Xor => lhs ^ rhs,
+            Self::Shl => lhs << rhs,
+            Self::Shr => lhs >> rhs,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CmpOp {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Clone, Debug)]
+pub enum Op<'a> {
+    /// Pushes a new value.
+    Const(Literal<'a>),
+    /// Pushes the value of a variable from its slot, or of a builtin if it has none.
+    Load(Span<Symbol>, Option<Slot>),
+    /// Replaces the top value with a copy of it, so that what is evaluated next cannot change it.
+    Snapshot,
+    Pop,
+    /// Fails if the variable already exists. `let` checks this before evaluating its value.
+    Fresh(Span<Symbol>),
+    /// Fails unless the variable exists. `=` checks this before evaluating its value.
+    Exists(Span<Symbol>, Option<Slot>),
+    /// Pops a value into a new variable, in its slot.
+    Let(Symbol, Option<Slot>, RawSpan),
+    /// Pops a value into an existing variable, respecting its lock and restriction.
+    Set(Span<Symbol>, Option<Slot>, RawSpan),
+    Binary(BinOp, RawSpan),
+    Compare(CmpOp, RawSpan),
+    /// `==`, or `!=` if set.
+    Eq(bool, RawSpan),
+    Neg(RawSpan),
+    Not(RawSpan),
+    Copy,
+    /// Pops this many values into an array.
+    Array(usize),
+    /// Fails unless the top value can be indexed, at the span of the parent.
+    Indexable(RawSpan),
+    /// Pops a key and a value, and pushes `value[key]`.
+    Index(RawSpan),
+    /// Checks that the top value can be called with arguments at these spans, before they are
+    /// evaluated.
+    Callee {
+        args: Vec<RawSpan>,
+        access: RawSpan,
+        span: RawSpan,
+    },
+    /// Pops the arguments and the function, and pushes what it returns.
+    Call {
+        args: Vec<RawSpan>,
+        span: RawSpan,
+    },
+    /// Pops this many values and prints them, followed by a newline if set.
+    Print(usize, bool, RawSpan),
+    /// Formats the top value with the spec of a hole, blaming the span for errors.
+    Format(Option<String>, RawSpan),
+    /// Pops one formatted value less than there are pieces of text, and joins them together.
+    Concat(Vec<String>),
+    Jump(usize),
+    /// Pops a condition and jumps if it is `false`. Fails with the message unless it is a `bool`.
+    JumpUnless(usize, RawSpan, &'static str),
+    /// Runs what follows in a child scope, which is `unsafe` if set.
+    Enter(bool),
+    Leave,
+    /// Pops the value of a `return`. `next` is the statement after it, which is never reached.
+    ///
+    /// If it returns a call, it pops the arguments and the fn instead, and leaves the call to the
+    /// caller of this fn. `call` is then the span of the call and of each argument.
+    Return {
+        span: RawSpan,
+        next: Option<RawSpan>,
+        call: Option<(RawSpan, Vec<RawSpan>)>,
+    },
+    /// Pushes the value of a tree, evaluated by the tree-walking interpreter.
+    Eval(Box<Span<Expr>>),
+}
+
+impl Op<'_> {
+    /// Whether it pushes a value that it made itself, which counts towards the limits of the
+    /// program.
+    #[must_use]
+    pub const fn makes_value(&self) -> bool {
+        matches!(
+            self,
+            Self::Const(_)
+                | Self::Snapshot
+                | Self::Binary(..)
+                | Self::Compare(..)
+                | Self::Eq(..)
+                | Self::Neg(_)
+                | Self::Not(_)
+                | Self::Copy
+                | Self::Array(_)
+                | Self::Call { .. }
+                | Self::Format(..)
+                | Self::Concat(_)
+        )
+    }
+}
+
+/// The bytecode of a list of statements.
+#[derive(Clone, Debug, Default)]
+pub struct Chunk<'a> {
+    pub ops: Vec<Op<'a>>,
+    /// The expression each instruction came from, blamed when running it goes over a limit.
+    pub spans: Vec<RawSpan>,
+}
+
+/// Compiles a list of statements, using the slots of `resolution` for its variables.
+#[must_use]
+pub fn compile<'a>(stmts: &[Span<Expr>], resolution: &Resolution) -> Chunk<'a> {
+    let mut compiler = Compiler {
+        chunk: Chunk::default(),
+        resolution,
+        span: RawSpan::from(0..0),
+    };
+
+    compiler.stmts(stmts);
+    compiler.chunk
+}
+
+struct Compiler<'a, 'r> {
+    chunk: Chunk<'a>,
+    resolution: &'r Resolution,
+    /// The expression being compiled.
+    span: RawSpan,
+}
+
+impl<'a> Compiler<'a, '_> {
+    fn emit(&mut self, op: Op<'a>) -> usize {
+        self.chunk.ops.push(op);
+        self.chunk.spans.push(self.span);
+        self.chunk.ops.len() - 1
+    }
+
+    /// Points the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let to = self.chunk.ops.len();
+
+        match &mut self.chunk.ops[at] {
+            Op::Jump(x) | Op::JumpUnless(x, ..) => *x = to,
+            _ => unreachable!(),
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Span<Expr>]) {
+        for (i, stmt) in stmts.iter().enumerate() {
+            self.stmt(stmt);
+
+            if let (Expr::Return { .. }, Some(Op::Return { next, .. })) =
+                (&stmt.0, self.chunk.ops.last_mut())
+            {
+                *next = stmts.get(i + 1).map(|x| x.1);
+            }
+        }
+    }
+
+    /// A statement, which leaves nothing on the stack.
+    fn stmt(&mut self, stmt: &Span<Expr>) {
+        grow(|| self.emit_stmt(stmt));
+    }
+
+    fn emit_stmt(&mut self, Span(stmt, span): &Span<Expr>) {
+        let span = *span;
+        let outer = std::mem::replace(&mut self.span, span);
+
+        match stmt {
+            Expr::Let { ident, expr, .. } if !matches!(expr.0, Expr::Restrict { .. }) => {
+                self.emit(Op::Fresh(ident.clone()));
+                self.expr(expr);
+                self.emit(Op::Let(ident.0.clone(), self.resolution.get(ident.1), span));
+            }
+
+            Expr::Assign { ident, expr, .. } => {
+                let at = self.resolution.get(ident.1);
+                self.emit(Op::Exists(ident.clone(), at));
+                self.expr(expr);
+                self.emit(Op::Set(ident.clone(), at, span));
+            }
+
+            Expr::AddAssign { ident, expr, .. } => self.update(BinOp::Add, ident, expr, span),
+            Expr::SubAssign { ident, expr, .. } => self.update(BinOp::Sub, ident, expr, span),
+            Expr::MulAssign { ident, expr, .. } => self.update(BinOp::Mul, ident, expr, span),
+            Expr::DivAssign { ident, expr, .. } => self.update(BinOp::Div, ident, expr, span),
+            Expr::RemAssign { ident, expr, .. } => self.update(BinOp::Rem, ident, expr, span),
+            Expr::AndAssign { ident, expr, .. } => self.update(BinOp::And, ident, expr, span),
+            Expr::OrAssign { ident, expr, .. } => self.update(BinOp::Or, ident, expr, span),
+            Expr::XorAssign { ident, expr, .. } => self.update(BinOp::Xor, ident, expr, span),
+            Expr::ShlAssign { ident, expr, .. } => self.update(BinOp::Shl, ident, expr, span),
+            Expr::ShrAssign { ident, expr, .. } => self.update(BinOp::Shr, ident, expr, span),
+
+            Expr::Print {
+                print_token,
+                values,
+                ..
+            } => {
+                for value in values {
+                    self.expr(value);
+                }
+
+                self.emit(Op::Print(
+                    values.len(),
+                    print_token.0 == Token::Println,
+                    span,
+                ));
+            }
+
+            Expr::If {
+                condition,
+                then,
+                other,
+                ..
+            } => {
+                self.expr(condition);
+                let skip = self.emit(Op::JumpUnless(
+                    0,
+                    condition.1,
+                    "condition of an `if` statement must be of type `bool`",
+                ));
+                self.stmt(then);
+
+                if let Some(other) = other {
+                    let end = self.emit(Op::Jump(0));
+                    self.patch(skip);
+                    self.stmt(other);
+                    self.patch(end);
+                } else {
+                    self.patch(skip);
+                }
+            }
+
+            Expr::While {
+                condition, then, ..
+            } => {
+                let start = self.chunk.ops.len();
+                self.expr(condition);
+                let end = self.emit(Op::JumpUnless(
+                    0,
+                    condition.1,
+                    "condition of an `while` statement must be of type `bool`",
+                ));
+                self.stmt(then);
+                self.emit(Op::Jump(start));
+                self.patch(end);
+            }
+
+            Expr::Block { exprs, .. } => self.block(exprs, false),
+            Expr::Unsafe { block, .. } => match &block.0 {
+                Expr::Block { exprs, .. } => self.block(exprs, true),
+                _ => self.eval(stmt, span),
+            },
+
+            Expr::Return { value, .. } => {
+                let call = if let Expr::FnCall {
+                    access, arguments, ..
+                } = &value.0
+                {
+                    Some((value.1, self.callee(access, arguments, value.1)))
+                } else {
+                    self.expr(value);
+                    None
+                };
+
+                self.emit(Op::Return {
+                    span,
+                    next: None,
+                    call,
+                });
+            }
+
+            Expr::Statement { expr, .. } => {
+                self.expr(expr);
+                self.emit(Op::Pop);
+            }
+
+            _ => self.eval(stmt, span),
+        }
+
+        self.span = outer;
+    }
+
+    /// `x += y` and the like.
+    fn update(&mut self, op: BinOp, ident: &Span<Symbol>, expr: &Span<Expr>, span: RawSpan) {
+        let at = self.resolution.get(ident.1);
+        self.emit(Op::Load(ident.clone(), at));
+        self.operand(expr);
+        self.emit(Op::Binary(op, span));
+        self.emit(Op::Set(ident.clone(), at, span));
+    }
+
+    fn eval(&mut self, stmt: &Expr, span: RawSpan) {
+        self.emit(Op::Eval(Box::new(Span(stmt.clone(), span))));
+        self.emit(Op::Pop);
+    }
+
+    /// A block. It only gets its own scope if something in it could declare a variable or print,
+    /// since what a scope prints is its own.
+    fn block(&mut self, exprs: &[Span<Expr>], is_unsafe: bool) {
+        let scoped = is_unsafe || !exprs.iter().all(|x| is_plain(&x.0));
+
+        if scoped {
+            self.emit(Op::Enter(is_unsafe));
+        }
+
+        self.stmts(exprs);
+
+        if scoped {
+            self.emit(Op::Leave);
+        }
+    }
+
+    /// The right-hand side of an operator, whose left-hand side is already on the stack. The tree
+    /// reads the left-hand side before evaluating this, so if this could change it, it is copied.
+    fn operand(&mut self, expr: &Span<Expr>) {
+        if !is_pure(&expr.0) {
+            self.emit(Op::Snapshot);
+        }
+
+        self.expr(expr);
+    }
+
+    fn binary(&mut self, lhs: &Span<Expr>, rhs: &Span<Expr>, op: Op<'a>) {
+        self.expr(lhs);
+        self.operand(rhs);
+        self.emit(op);
+    }
+
+    /// An expression, which leaves its value on the stack.
+    fn expr(&mut self, expr: &Span<Expr>) {
+        grow(|| self.emit_expr(expr));
+    }
+
+    fn emit_expr(&mut self, Span(expr, span): &Span<Expr>) {
+        let span = *span;
+        let outer = std::mem::replace(&mut self.span, span);
+
+        match expr {
+            Expr::Literal { value } => {
+                self.emit(Op::Const(value.clone().into()));
+            }
+            Expr::Access { ident } => {
+                self.emit(Op::Load(ident.clone(), self.resolution.get(ident.1)));
+            }
+            Expr::Group { expr, .. } => self.expr(expr),
+
+            Expr::Add { lhs, rhs, .. } => self.binary(lhs, rhs, Op::Binary(BinOp::Add, span)),
+            Expr::Sub { lhs, rhs, .. } => self.binary(lhs, rhs, Op::Binary(BinOp::Sub, span)),
+            Expr::Mul { lhs, rhs, .. } => self.binary(lhs, rhs, Op::Binary(BinOp::Mul, span)),
+            Expr::Div { lhs, rhs, .. } => self.binary(lhs, rhs, Op::Binary(BinOp::Div, span)),
+            Expr::Rem { lhs, rhs, .. } => self.binary(lhs, rhs, Op::Binary(BinOp::Rem, span)),
+            Expr::And { lhs, rhs, .. } => self.binary(lhs, rhs, Op::Binary(BinOp::And, span)),
+            Expr::Or { lhs, rhs, .. } => self.binary(lhs, rhs, Op::Binary(BinOp::Or, span)),
+            Expr::Xor { lhs, rhs, .. } => self.binary(lhs, rhs, Op::Binary(BinOp::Xor, span)),
+            Expr::Shl { lhs, rhs, .. } => self.binary(lhs, rhs, Op::Binary(BinOp::Shl, span)),
+            Expr::Shr { lhs, rhs, .. } => self.binary(lhs, rhs, Op::Binary(BinOp::Shr, span)),
+            Expr::Eq { lhs, rhs, .. } => self.binary(lhs, rhs, Op::Eq(false, span)),
+            Expr::Ne { lhs, rhs, .. } => self.binary(lhs, rhs, Op::Eq(true, span)),
+
+            // these read both sides after evaluating them, so nothing needs a copy
+            Expr::Gt { lhs, rhs, .. } => self.compare(lhs, rhs, CmpOp::Gt, span),
+            Expr::Ge { lhs, rhs, .. } => self.compare(lhs, rhs, CmpOp::Ge, span),
+            Expr::Lt { lhs, rhs, .. } => self.compare(lhs, rhs, CmpOp::Lt, span),
+            Expr::Le { lhs, rhs, .. } => self.compare(lhs, rhs, CmpOp::Le, span),
+
+            Expr::Neg { expr, .. } => {
+                self.expr(expr);
+                self.emit(Op::Neg(span));
+            }
+            Expr::Not { expr, .. } => {
+                self.expr(expr);
+                self.emit(Op::Not(span));
+            }
+            Expr::Copy { expr, .. } => {
+                self.expr(expr);
+                self.emit(Op::Copy);
+            }
+
+            Expr::Array { exprs, .. } => {
+                for expr in exprs {
+                    self.expr(expr);
+                }
+
+                self.emit(Op::Array(exprs.len()));
+            }
+
+            Expr::Chain { parent, child } => {
+                self.expr(parent);
+                self.emit(Op::Indexable(parent.1));
+                self.operand(child);
+                self.emit(Op::Index(child.1));
+            }
+
+            Expr::FnCall {
+                access, arguments, ..
+            } => {
+                let args = self.callee(access, arguments, span);
+                self.emit(Op::Call { args, span });
+            }
+
+            Expr::Interp { start, holes } => {
+                let mut texts = vec![text(&start.0)];
+
+                for (expr, spec, after) in holes {
+                    self.expr(expr);
+                    self.emit(match spec {
+                        Some(Span(Token::FormatSpec(x), s)) => Op::Format(Some(x.clone()), *s),
+                        _ => Op::Format(None, expr.1),
+                    });
+                    texts.push(text(&after.0));
+                }
+
+                self.emit(Op::Concat(texts));
+            }
+
+            Expr::Let { .. }
+            | Expr::Assign { .. }
+            | Expr::AddAssign { .. }
+            | Expr::SubAssign { .. }
+            | Expr::MulAssign { .. }
+            | Expr::DivAssign { .. }
+            | Expr::RemAssign { .. }
+            | Expr::AndAssign { .. }
+            | Expr::OrAssign { .. }
+            | Expr::XorAssign { .. }
+            | Expr::ShlAssign { .. }
+            | Expr::ShrAssign { .. }
+            | Expr::Print { .. }
+            | Expr::If { .. }
+            | Expr::While { .. }
+            | Expr::Block { .. }
+            | Expr::Unsafe { .. }
+            | Expr::Statement { .. } => {
+                self.stmt(&Span(expr.clone(), span));
+                self.emit(Op::Const(Literal::None));
+            }
+
+            _ => {
+                self.emit(Op::Eval(Box::new(Span(expr.clone(), span))));
+            }
+        }
+
+        self.span = outer;
+    }
+
+    /// Pushes what a call calls and its arguments, and gives back the spans of the arguments.
+    fn callee(
+        &mut self,
+        access: &Span<Expr>,
+        arguments: &[Span<Expr>],
+        span: RawSpan,
+    ) -> Vec<RawSpan> {
+        let args = arguments.iter().map(|x| x.1).collect::<Vec<_>>();
+
+        self.expr(access);
+        self.emit(Op::Callee {
+            args: args.clone(),
+            access: access.1,
+            span,
+        });
+
+        for arg in arguments {
+            self.expr(arg);
+        }
+
+        args
+    }
+
+    fn compare(&mut self, lhs: &Span<Expr>, rhs: &Span<Expr>, op: CmpOp, span: RawSpan) {
+        self.expr(lhs);
+        self.expr(rhs);
+        self.emit(Op::Compare(op, span));
+    }
+}
+
+/// Whether a statement cannot declare a variable in its scope.
+#[must_use]
+pub const fn is_plain(stmt: &Expr) -> bool {
+    matches!(
+        stmt,
+        Expr::Assign { .. }
+            | Expr::AddAssign { .. }
+            | Expr::SubAssign { .. }
+            | Expr::MulAssign { .. }
+            | Expr::DivAssign { .. }
+            | Expr::RemAssign { .. }
+            | Expr::AndAssign { .. }
+            | Expr::OrAssign { .. }
+            | Expr::XorAssign { .. }
+            | Expr::ShlAssign { .. }
+            | Expr::ShrAssign { .. }
+            | Expr::Print { .. }
+            | Expr::If { .. }
+            | Expr::While { .. }
+            | Expr::Block { .. }
+            | Expr::Unsafe { .. }
+            | Expr::Return { .. }
+            | Expr::Statement { .. }
+    )
+}
+
+/// Whether evaluating an expression cannot change any value, i.e. it calls nothing.
+fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal { .. } | Expr::Access { .. } => true,
+        Expr::Group { expr, .. } | Expr::Neg { expr, .. } | Expr::Not { expr, .. } => {
+            is_pure(&expr.0)
+        }
+        Expr::Add { lhs, rhs, .. }
+        | Expr::Sub { lhs, rhs, .. }
+        | Expr::Mul { lhs, rhs, .. }
+        | Expr::Div { lhs, rhs, .. }
+        | Expr::Rem { lhs, rhs, .. }
+        | Expr::And { lhs, rhs, .. }
+        | Expr::Or { lhs, rhs, .. }
+        | Expr::Xor { lhs, rhs, .. }
+        | Expr::Shl { lhs, rhs, .. }
+        | Expr::Shr { lhs, rhs, .. }
+        | Expr::Eq { lhs, rhs, .. }
+        | Expr::Ne { lhs, rhs, .. } => is_pure(&lhs.0) && is_pure(&rhs.0),
+        Expr::Chain { parent, child } => is_pure(&parent.0) && is_pure(&child.0),
+        Expr::Array { exprs, .. } => exprs.iter().all(|x| is_pure(&x.0)),
+        _ => false,
+    }
+}
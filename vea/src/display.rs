@@ -1,8 +1,12 @@
 use std::fmt::Display;
 
-use crate::{ast::Expr, lexer::Token, literal::Literal};
+use crate::{
+    ast::{Constant, Expr, Pattern},
+    lexer::Token,
+    literal::Literal,
+};
 
-impl<'a> Display for Token<'a> {
+impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
@@ -42,6 +46,7 @@ impl<'a> Display for Token<'a> {
                 Self::Plus => "+".to_string(),
                 Self::PlusEq => "++".to_string(),
                 Self::Print => "print".to_string(),
+                Self::Println => "println".to_string(),
                 Self::Question => "?".to_string(),
                 Self::Quote => "'".to_string(),
                 Self::Return => "return".to_string(),
@@ -50,6 +55,10 @@ impl<'a> Display for Token<'a> {
                 Self::RightParen => ")".to_string(),
                 Self::Semi => ";".to_string(),
                 Self::Set => "set".to_string(),
+                Self::Lock => "lock".to_string(),
+                Self::Use => "use".to_string(),
+                Self::Unsafe => "unsafe".to_string(),
+                Self::This => "self".to_string(),
                 Self::Shl => "<<".to_string(),
                 Self::ShlEq => "<<=".to_string(),
                 Self::Shr => ">>".to_string(),
@@ -58,9 +67,18 @@ impl<'a> Display for Token<'a> {
                 Self::SlashEq => "/=".to_string(),
                 Self::Star => "*".to_string(),
                 Self::StarEq => "*=".to_string(),
-                Self::String(v) => format!("'{v}'"),
+                Self::String(v) => format!("'{}'", escape(v)),
+                Self::InterpStart(v) => format!("'{}{{", escape(v)),
+                Self::InterpMid(v) => format!("}}{}{{", escape(v)),
+                Self::InterpEnd(v) => format!("}}{}'", escape(v)),
+                Self::FormatSpec(v) => format!(":{v}"),
                 Self::Struct => "struct".to_string(),
                 Self::Tilde => "~".to_string(),
+                Self::GtTilde => ">~".to_string(),
+                Self::GeTilde => ">=~".to_string(),
+                Self::LtTilde => "<~".to_string(),
+                Self::LeTilde => "<=~".to_string(),
+                Self::EqEqTilde => "==~".to_string(),
                 Self::True => "true".to_string(),
                 Self::Underscore => "_".to_string(),
                 Self::While => "while".to_string(),
@@ -73,7 +91,12 @@ impl<'a> Display for Token<'a> {
     }
 }
 
-impl<'a> Expr<'a> {
+/// Doubles the braces of string text, so that they are not read as holes.
+fn escape(text: &str) -> String {
+    text.replace('{', "{{").replace('}', "}}")
+}
+
+impl Expr {
     fn disp(&self, depth: usize) -> String {
         let mut m = String::from("\t").repeat(depth);
 
@@ -84,56 +107,36 @@ impl<'a> Expr<'a> {
                 print_token,
                 right_paren,
                 semi_token,
-                value,
-            } => format!("{print_token}{left_paren}{value}{right_paren}{semi_token}"),
+                values,
+            } => format!(
+                "{print_token}{left_paren}{}{right_paren}{semi_token}",
+                values
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::Interp { start, holes } => {
+                let mut out = start.to_string();
+
+                for (expr, spec, text) in holes {
+                    out += &expr.to_string();
+
+                    if let Some(spec) = spec {
+                        out += &spec.to_string();
+                    }
+
+                    out += &text.to_string();
+                }
+
+                out
+            }
             Self::Return {
                 return_token,
                 value,
                 semi_token,
             } => format!("{return_token} {value}{semi_token}"),
-            Self::Literal { value } => match value {
-                Literal::Fn(name, ..) => name.0.to_string(),
-                Literal::Object(values) => {
-                    format!(
-                        "struct {{\n{}\n}}",
-                        values
-                            .iter()
-                            .map(|(k, v)| {
-                                match v.borrow().clone() {
-                                    Literal::Fn(name, args, body) => format!(
-                                        "{}fn {name}({}) {body}",
-                                        "\t".repeat(depth + 1),
-                                        args.iter()
-                                            .map(ToString::to_string)
-                                            .collect::<Vec<_>>()
-                                            .join("\n")
-                                    ),
-                                    c => format!(
-                                        "{}let {k} = {};",
-                                        "\t".repeat(depth + 1),
-                                        Self::Literal { value: c }.disp(depth + 1)
-                                    ),
-                                }
-                            })
-                            .collect::<Vec<_>>()
-                            .join("\n")
-                    )
-                }
-                Literal::Set(values) => {
-                    format!(
-                        "set {{\n{}\n}}",
-                        values
-                            .iter()
-                            .map(|x| Expr::Literal {
-                                value: x.borrow().clone()
-                            }
-                            .disp(depth + 1))
-                            .collect::<Vec<_>>()
-                            .join("\n")
-                    )
-                }
-                c => c.to_string(),
-            },
+            Self::Literal { value } => value.to_string(),
             Self::Object {
                 struct_token,
                 left_brace,
@@ -160,6 +163,18 @@ impl<'a> Expr<'a> {
                     .collect::<Vec<_>>()
                     .join("\n")
             ),
+            Self::Array {
+                left_bracket,
+                exprs,
+                right_bracket,
+            } => format!(
+                "{left_bracket}{}{right_bracket}",
+                exprs
+                    .iter()
+                    .map(|x| x.0.disp(depth))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             Self::Group {
                 left_paren,
                 expr,
@@ -202,6 +217,8 @@ impl<'a> Expr<'a> {
                 then,
             } => format!("{while_token} ({condition}) {then}"),
             Self::FnDecl {
+                test_token,
+                unsafe_token,
                 fn_token,
                 name,
                 left_paren,
@@ -209,13 +226,56 @@ impl<'a> Expr<'a> {
                 right_paren,
                 block,
             } => format!(
-                "{fn_token} {name}{left_paren}{}{right_paren} {block}",
+                "{}{}{fn_token} {name}{left_paren}{}{right_paren} {block}",
+                test_token
+                    .as_ref()
+                    .map_or(String::new(), |x| format!("{x} ")),
+                unsafe_token
+                    .as_ref()
+                    .map_or(String::new(), |x| format!("{x} ")),
+                arguments
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::Method {
+                unsafe_token,
+                self_token,
+                name,
+                left_paren,
+                arguments,
+                right_paren,
+                block,
+            } => format!(
+                "{}{}{name}{left_paren}{}{right_paren} {block}",
+                unsafe_token
+                    .as_ref()
+                    .map_or(String::new(), |x| format!("{x} ")),
+                self_token
+                    .as_ref()
+                    .map_or(String::new(), |x| format!("{x}::")),
                 arguments
                     .iter()
                     .map(ToString::to_string)
                     .collect::<Vec<_>>()
                     .join(", ")
             ),
+            Self::LetSelf {
+                let_token,
+                self_token,
+                ident,
+                eq_token,
+                expr,
+                semi_token,
+            } => format!("{let_token} {self_token}::{ident} {eq_token} {expr}{semi_token}"),
+            Self::This { self_token } => self_token.to_string(),
+            Self::Unsafe {
+                unsafe_token,
+                block,
+            } => format!("{unsafe_token} {}", block.0.disp(depth)),
+            Self::Empty { underscore_token } => underscore_token.to_string(),
+            Self::Statement { expr, semi_token } => format!("{expr}{semi_token}"),
             Self::FnCall {
                 access,
                 left_paren,
@@ -302,8 +362,31 @@ impl<'a> Expr<'a> {
                 expr,
                 semi_token,
             } => format!("{ident} {pipe_eq_token} {expr}{semi_token}"),
+            Self::IndexAssign {
+                target,
+                eq_token,
+                expr,
+                semi_token,
+            } => format!("{target} {eq_token} {expr}{semi_token}"),
+            Self::Use {
+                use_token,
+                path,
+                semi_token,
+            } => format!(
+                "{use_token} {}{semi_token}",
+                path.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("::")
+            ),
+            Self::Lock {
+                lock_token,
+                target,
+                semi_token,
+            } => format!("{lock_token} {target}{semi_token}"),
             Self::Not { bang_token, expr } => format!("{bang_token}{expr}"),
             Self::Neg { minus_token, expr } => format!("{minus_token}{expr}"),
+            Self::Copy { and_token, expr } => format!("{and_token}{expr}"),
 
             Self::Add {
                 lhs,
@@ -365,8 +448,60 @@ impl<'a> Expr<'a> {
             Self::Ge { lhs, ge_token, rhs } => format!("{lhs} {ge_token} {rhs}"),
             Self::Lt { lhs, lt_token, rhs } => format!("{lhs} {lt_token} {rhs}"),
             Self::Le { lhs, le_token, rhs } => format!("{lhs} {le_token} {rhs}"),
+            Self::AlmostGt {
+                lhs,
+                gt_tilde_token,
+                epsilon,
+                rhs,
+            } => format!("{lhs} {gt_tilde_token}{epsilon} {rhs}"),
+            Self::AlmostGe {
+                lhs,
+                ge_tilde_token,
+                epsilon,
+                rhs,
+            } => format!("{lhs} {ge_tilde_token}{epsilon} {rhs}"),
+            Self::AlmostLt {
+                lhs,
+                lt_tilde_token,
+                epsilon,
+                rhs,
+            } => format!("{lhs} {lt_tilde_token}{epsilon} {rhs}"),
+            Self::AlmostLe {
+                lhs,
+                le_tilde_token,
+                epsilon,
+                rhs,
+            } => format!("{lhs} {le_tilde_token}{epsilon} {rhs}"),
+            Self::AlmostEq {
+                lhs,
+                eqeq_tilde_token,
+                epsilon,
+                rhs,
+            } => format!("{lhs} {eqeq_tilde_token}{epsilon} {rhs}"),
+            Self::Restrict {
+                expr,
+                left_brace,
+                pred,
+                right_brace,
+            } => format!("{expr}{left_brace}{pred}{right_brace}"),
+            Self::Is {
+                lhs,
+                question_token,
+                pattern,
+            } => format!("{lhs}{question_token}{pattern}"),
 
             Self::Chain { parent, child } => format!("{parent}:{child}"),
+            Self::Slice {
+                parent,
+                start,
+                end,
+                inclusive,
+            } => format!(
+                "{parent}[{}..{}{}]",
+                start.as_ref().map_or(String::new(), ToString::to_string),
+                if *inclusive { "=" } else { "" },
+                end.as_ref().map_or(String::new(), ToString::to_string),
+            ),
 
             Self::None => String::from("_"),
             Self::Error(..) => String::from("@"),
@@ -376,10 +511,71 @@ impl<'a> Expr<'a> {
         m
     }
 }
-impl<'a> Display for Expr<'a> {
+impl Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let x = self.disp(0);
 
         write!(f, "{x}")
     }
 }
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Literal::from(self.clone()))
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Wildcard => write!(f, "_"),
+            Self::Literal { value } => match value {
+                Constant::String(s) => write!(f, "'{s}'"),
+                c => write!(f, "{c}"),
+            },
+            Self::Range {
+                start,
+                end,
+                inclusive,
+            } => write!(f, "{start}..{}{end}", if *inclusive { "=" } else { "" }),
+            Self::Ident { ident } => write!(f, "{ident}"),
+            Self::Value { expr } => write!(f, "({expr})"),
+            Self::Object { fields } => write!(
+                f,
+                "struct {{ {} }}",
+                fields
+                    .iter()
+                    .map(|(k, v)| format!("{k}: {v}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::Set { items } => write!(
+                f,
+                "set {{ {} }}",
+                items
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::Array { items } => write!(
+                f,
+                "[{}]",
+                items
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::Or { alternatives } => write!(
+                f,
+                "{}",
+                alternatives
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            ),
+        }
+    }
+}
@@ -1,15 +1,168 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    io,
+    rc::Rc,
+};
 
-use crate::literal::Literal;
+use crate::{
+    ast::Expr,
+    gc::Heap,
+    input::{Input, Text},
+    interpreter::Tail,
+    interrupt::Interrupt,
+    limits::Meter,
+    literal::Literal,
+    module::Modules,
+    output::{Buffer, Output},
+    resolver::{Resolution, Slot},
+    span::Span,
+    vm::{Backend, Chunks},
+};
+
+type Value<'a> = Rc<RefCell<Literal<'a>>>;
+
+/// The variables of one scope, each in the place the resolver gave it, its slot.
+///
+/// A slot stays empty until its variable is declared, which may never happen, e.g. for a field of
+/// `self` in a branch that didn't run.
+#[derive(Debug, Clone, Default)]
+pub struct Frame<'a> {
+    slots: Vec<Option<(String, Value<'a>)>>,
+    names: HashMap<String, usize>,
+}
+
+impl<'a> Frame<'a> {
+    #[must_use]
+    pub fn get(&self, k: &str) -> Option<&Value<'a>> {
+        self.names.get(k).and_then(|&i| self.slot(i))
+    }
+
+    pub fn get_mut(&mut self, k: &str) -> Option<&mut Value<'a>> {
+        let i = *self.names.get(k)?;
+        self.slot_mut(i)
+    }
+
+    /// The value in `slot`, unless its variable isn't declared yet.
+    #[must_use]
+    pub fn slot(&self, slot: usize) -> Option<&Value<'a>> {
+        self.slots.get(slot)?.as_ref().map(|x| &x.1)
+    }
+
+    pub fn slot_mut(&mut self, slot: usize) -> Option<&mut Value<'a>> {
+        self.slots.get_mut(slot)?.as_mut().map(|x| &mut x.1)
+    }
+
+    /// Declares `k` in the slot that the resolver gave it.
+    pub fn declare(&mut self, at: Option<Slot>, k: &str, v: Value<'a>) -> Result<(), String> {
+        let Some((_, slot)) = at else {
+            return Err(format!("internal error: `{k}` was never resolved"));
+        };
+
+        if self.slots.len() <= slot {
+            self.slots.resize_with(slot + 1, || None);
+        }
+
+        self.slots[slot] = Some((k.to_owned(), v));
+        self.names.insert(k.to_owned(), slot);
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn contains_key(&self, k: &str) -> bool {
+        self.names.contains_key(k)
+    }
+
+    /// Declares `k` after the last slot, or replaces its value if it already has one.
+    pub fn insert(&mut self, k: String, v: Value<'a>) {
+        if let Some(x) = self.get_mut(&k) {
+            *x = v;
+            return;
+        }
+
+        self.names.insert(k.clone(), self.slots.len());
+        self.slots.push(Some((k, v)));
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The names, in slot order.
+    pub fn keys(&self) -> impl Iterator<Item = &String> + use<'_, 'a> {
+        self.iter().map(|x| x.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value<'a>)> + use<'_, 'a> {
+        self.slots.iter().flatten().map(|(k, v)| (k, v))
+    }
+
+    /// The same variables in the same slots, with each value replaced by `f(value)`.
+    #[must_use]
+    pub fn map(&self, mut f: impl FnMut(&Value<'a>) -> Value<'a>) -> Self {
+        Self {
+            slots: self
+                .slots
+                .iter()
+                .map(|x| x.as_ref().map(|(k, v)| (k.clone(), f(v))))
+                .collect(),
+            names: self.names.clone(),
+        }
+    }
+
+    /// The name in every slot, or `None` where it is still empty.
+    pub fn layout(&self) -> impl Iterator<Item = Option<&String>> + use<'_, 'a> {
+        self.slots.iter().map(|x| x.as_ref().map(|x| &x.0))
+    }
+}
+
+/// The variables of one scope. Shared with the `env` values that `self` produces.
+pub type Scope<'a> = Rc<RefCell<Frame<'a>>>;
 
 #[derive(Debug, Clone)]
 pub struct Env<'a> {
     pub name: Option<String>,
-    pub values: Rc<RefCell<HashMap<String, Rc<RefCell<Literal<'a>>>>>>,
+    pub values: Scope<'a>,
     pub retval: Rc<RefCell<HashMap<String, Rc<RefCell<Literal<'a>>>>>>,
-    pub stdout: String,
+    pub locked: Rc<RefCell<HashSet<String>>>,
+    pub refinements: Rc<RefCell<HashMap<String, Span<Expr>>>>,
+    /// What `print` writes to. Every scope of a program shares it.
+    pub stdout: Rc<RefCell<dyn Output>>,
+    /// What `eprint()` writes to.
+    pub stderr: Rc<RefCell<dyn Output>>,
+    /// What the program has used of its limits.
+    pub meter: Rc<Meter>,
+    /// The values of the program that could end up in a cycle.
+    pub heap: Rc<Heap<'a>>,
+    /// Stops the program when it is cancelled.
+    pub interrupt: Interrupt,
+    /// Where a `return f(...)` leaves its call, inside of a fn.
+    pub tail: Option<Tail<'a>>,
     pub parent: Option<Rc<RefCell<Self>>>,
     pub retyet: bool,
+    pub modules: Rc<RefCell<Modules<'a>>>,
+    /// Functions like `cmp` that every scope can see, but that variables are free to shadow.
+    pub builtins: Scope<'a>,
+    /// What `self` refers to: the scope of the current fn, or of the object a method belongs to.
+    pub this: Option<Scope<'a>>,
+    /// Whether this scope is inside an `unsafe` block or the body of an unsafe fn.
+    pub is_unsafe: bool,
+    /// Where an error passed through on its way out, innermost first, like the callback it was
+    /// raised in.
+    pub trace: Rc<RefCell<Vec<Span<String>>>>,
+    /// What `input()` reads from. Nothing, unless it is set.
+    pub input: Rc<RefCell<dyn Input>>,
+    pub backend: Backend,
+    /// What the VM has compiled so far.
+    pub chunks: Chunks<'a>,
+    /// Where the resolver found the variables of every file run so far.
+    pub resolution: Rc<RefCell<Resolution>>,
 }
 
 impl<'a> Env<'a> {
@@ -18,16 +171,64 @@ impl<'a> Env<'a> {
         Self {
             name,
             parent: None,
-            stdout: String::default(),
-            values: Rc::new(RefCell::new(HashMap::default())),
+            stdout: Rc::new(RefCell::new(Buffer::default())),
+            stderr: Rc::new(RefCell::new(Buffer::default())),
+            meter: Rc::new(Meter::default()),
+            heap: Rc::new(Heap::default()),
+            interrupt: Interrupt::default(),
+            tail: None,
+            values: Rc::new(RefCell::new(Frame::default())),
             retval: Rc::new(RefCell::new(HashMap::default())),
+            locked: Rc::new(RefCell::new(HashSet::default())),
+            refinements: Rc::new(RefCell::new(HashMap::default())),
             retyet: false,
+            modules: Rc::new(RefCell::new(Modules::default())),
+            builtins: Rc::new(RefCell::new(Frame::default())),
+            this: None,
+            is_unsafe: false,
+            trace: Rc::new(RefCell::new(Vec::new())),
+            input: Rc::new(RefCell::new(Text::default())),
+            backend: Backend::default(),
+            chunks: Rc::new(RefCell::new(HashMap::default())),
+            resolution: Rc::new(RefCell::new(Resolution::default())),
         }
     }
+    /// A scope with no variables and no parent that belongs to the same program as `program`. It
+    /// shares the output, limits, heap, modules and the rest of what is kept for the whole program.
+    #[must_use]
+    pub fn sharing(name: Option<String>, program: &Self) -> Self {
+        Self {
+            modules: program.modules.clone(),
+            builtins: program.builtins.clone(),
+            trace: program.trace.clone(),
+            input: program.input.clone(),
+            stdout: program.stdout.clone(),
+            stderr: program.stderr.clone(),
+            meter: program.meter.clone(),
+            heap: program.heap.clone(),
+            interrupt: program.interrupt.clone(),
+            backend: program.backend,
+            chunks: program.chunks.clone(),
+            resolution: program.resolution.clone(),
+            ..Self::new(name)
+        }
+    }
+
     pub fn with_parent(name: Option<String>, parent: Rc<RefCell<Self>>) -> Self {
+        let env = {
+            let parent = parent.borrow();
+
+            Self {
+                this: parent.this.clone(),
+                is_unsafe: parent.is_unsafe,
+                tail: parent.tail.clone(),
+                ..Self::sharing(name, &parent)
+            }
+        };
+
         Self {
             parent: Some(parent),
-            ..Self::new(name)
+            ..env
         }
     }
 
@@ -41,7 +242,85 @@ impl<'a> Env<'a> {
             return p.borrow().get(k);
         }
 
-        None
+        self.builtins.borrow().get(k).cloned()
+    }
+
+    /// Runs `f` on the scope `depth` levels up.
+    fn at_depth<T>(&self, depth: usize, f: impl FnOnce(&Self) -> T) -> Result<T, String> {
+        if depth == 0 {
+            return Ok(f(self));
+        }
+
+        let Some(p) = &self.parent else {
+            return Err(format!(
+                "internal error: a variable was resolved to a scope {depth} levels above the outermost one"
+            ));
+        };
+
+        p.borrow().at_depth(depth - 1, f)
+    }
+
+    /// The variable in the slot the resolver found for it, or `None` if it isn't declared yet.
+    pub fn slot(&self, (depth, slot): Slot) -> Result<Option<Value<'a>>, String> {
+        self.at_depth(depth, |env| env.values.borrow().slot(slot).cloned())
+    }
+
+    /// The value of the variable `k`. Without a slot, it can only be a builtin.
+    pub fn load(&self, at: Option<Slot>, k: &str) -> Result<Value<'a>, String> {
+        let value = match at {
+            Some(at) => self.slot(at)?,
+            None => self.builtins.borrow().get(k).cloned(),
+        };
+
+        value.ok_or_else(|| format!("variable `{k}` does not exist"))
+    }
+
+    /// Whether the variable at `at` is declared yet. A builtin never is, as it can't be assigned.
+    pub fn exists(&self, at: Option<Slot>) -> Result<bool, String> {
+        at.map_or(Ok(false), |at| Ok(self.slot(at)?.is_some()))
+    }
+
+    /// Assigns to the variable `k` in the slot the resolver found for it.
+    pub fn set_at(&self, at: Option<Slot>, k: &str, v: Value<'a>) -> Result<(), String> {
+        let (depth, slot) = at.ok_or_else(|| format!("variable `{k}` does not exist"))?;
+
+        self.at_depth(depth, |env| {
+            if env.locked.borrow().contains(k) {
+                return Err(format!("cannot assign to `{k}` because it is locked"));
+            }
+
+            let mut values = env.values.borrow_mut();
+            let x = values
+                .slot_mut(slot)
+                .ok_or_else(|| format!("variable `{k}` does not exist"))?;
+
+            *x = v;
+            Ok(())
+        })?
+    }
+
+    /// The predicate of `let k = x{pred};`, if the variable `k` at `at` has one.
+    pub fn refinement_at(&self, at: Option<Slot>, k: &str) -> Result<Option<Span<Expr>>, String> {
+        at.map_or(Ok(None), |(depth, _)| {
+            self.at_depth(depth, |env| env.refinements.borrow().get(k).cloned())
+        })
+    }
+
+    /// Whether the variable `k` at `at` is locked.
+    pub fn is_locked_at(&self, at: Option<Slot>, k: &str) -> Result<bool, String> {
+        at.map_or(Ok(false), |(depth, _)| {
+            self.at_depth(depth, |env| env.locked.borrow().contains(k))
+        })
+    }
+
+    /// Locks the variable `k` in the scope that declares it.
+    pub fn lock_at(&self, at: Option<Slot>, k: &str) -> Result<(), String> {
+        match at {
+            Some(at) if self.slot(at)?.is_some() => self.at_depth(at.0, |env| {
+                env.locked.borrow_mut().insert(k.to_owned());
+            }),
+            _ => Err(format!("variable `{k}` does not exist")),
+        }
     }
 
     #[must_use]
@@ -57,24 +336,31 @@ impl<'a> Env<'a> {
         None
     }
 
-    pub fn assign(&mut self, k: &str, v: Rc<RefCell<Literal<'a>>>) -> Result<(), String> {
-        if self.get(k).is_some() {
-            return Err(format!("variable `{k}` already exists"))
+    /// Declares `k` in this scope, in the slot the resolver gave it.
+    pub fn assign(
+        &mut self,
+        k: &str,
+        at: Option<Slot>,
+        v: Rc<RefCell<Literal<'a>>>,
+    ) -> Result<(), String> {
+        if self.has(k) {
+            return Err(format!("variable `{k}` already exists"));
         }
 
         if let Some(p) = &self.parent {
             if p.borrow().has(k) {
-                return Err(format!("variable `{k}` shadows an outer variable with the same name"));
+                return Err(format!(
+                    "variable `{k}` shadows an outer variable with the same name"
+                ));
             }
         }
 
-        self.values.borrow_mut().insert(k.to_owned(), v);
-        Ok(())
+        self.values.borrow_mut().declare(at, k, v)
     }
 
-    pub fn set(&mut self, k: &str, v: Rc<RefCell<Literal<'a>>>) -> bool {
+    /// Binds `k` in this scope, even if an outer scope already has a `k`.
+    pub fn bind(&mut self, k: &str, v: Rc<RefCell<Literal<'a>>>) {
         self.values.borrow_mut().insert(k.to_owned(), v);
-        true
     }
 
     pub fn set_ret(&mut self, k: &str, v: Rc<RefCell<Literal<'a>>>) -> bool {
@@ -95,12 +381,16 @@ impl<'a> Env<'a> {
         false
     }
 
-    pub fn print(&mut self, text: &str) -> &mut Self {
-        self.stdout += text;
-        self
+    /// Attaches the predicate of `let k = x{pred};` to `k`, to be checked on every assignment.
+    pub fn refine(&mut self, k: &str, pred: Span<Expr>) {
+        self.refinements.borrow_mut().insert(k.to_owned(), pred);
+    }
+
+    pub fn print(&self, text: &str) -> io::Result<()> {
+        self.stdout.borrow_mut().write(text)
     }
 
-    pub fn println(&mut self, text: &str) -> &mut Self {
-        self.print(text).print("\n")
+    pub fn eprint(&self, text: &str) -> io::Result<()> {
+        self.stderr.borrow_mut().write(text)
     }
 }
@@ -0,0 +1,249 @@
+//! Format specs for the holes of interpolated strings, like `{n:>8}` or `{n:08b}`.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    common::Tag,
+    limits::Meter,
+    literal::Literal,
+    span::{RawSpan, Span},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// How a value is written, before it is padded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Display,
+    /// `?`, which quotes strings
+    Debug,
+    /// `x`
+    Hex,
+    /// `X`
+    UpperHex,
+    /// `o`
+    Octal,
+    /// `b`
+    Binary,
+}
+
+/// `[[fill]align][0][width][kind]`, where `align` is one of `<^>`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Spec {
+    pub fill: char,
+    pub align: Option<Align>,
+    /// Pads numbers with zeroes after their sign.
+    pub zero: bool,
+    /// At most `u32::MAX`, so that padding can't overflow.
+    pub width: usize,
+    pub kind: Kind,
+}
+
+impl Default for Spec {
+    fn default() -> Self {
+        Self {
+            fill: ' ',
+            align: None,
+            zero: false,
+            width: 0,
+            kind: Kind::Display,
+        }
+    }
+}
+
+const fn align(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '^' => Some(Align::Center),
+        '>' => Some(Align::Right),
+        _ => None,
+    }
+}
+
+impl Spec {
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut spec = Self::default();
+        let mut rest = src;
+
+        let mut chars = rest.chars();
+        let (first, second) = (chars.next(), chars.next());
+
+        if let Some(a) = second.and_then(align) {
+            spec.fill = first.unwrap_or(' ');
+            spec.align = Some(a);
+            rest = &rest[first.map_or(0, char::len_utf8) + 1..];
+        } else if let Some(a) = first.and_then(align) {
+            spec.align = Some(a);
+            rest = &rest[1..];
+        }
+
+        if let Some(r) = rest.strip_prefix('0') {
+            spec.zero = true;
+            rest = r;
+        }
+
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+
+        if digits > 0 {
+            spec.width = rest[..digits]
+                .parse::<u32>()
+                .ok()
+                .and_then(|x| usize::try_from(x).ok())
+                .ok_or_else(|| format!("width `{}` is too large", &rest[..digits]))?;
+            rest = &rest[digits..];
+        }
+
+        spec.kind = match rest {
+            "" => Kind::Display,
+            "?" => Kind::Debug,
+            "x" => Kind::Hex,
+            "X" => Kind::UpperHex,
+            "o" => Kind::Octal,
+            "b" => Kind::Binary,
+            _ => return Err(format!("invalid format spec `{src}`")),
+        };
+
+        Ok(spec)
+    }
+
+    /// Writes `value` the way this spec asks for, checking the padded size against `meter` first.
+    pub fn format(
+        &self,
+        value: &Literal<'_>,
+        meter: &Meter,
+        span: RawSpan,
+    ) -> Result<String, Span<String>> {
+        let (sign, body) = match (self.kind, value) {
+            (Kind::Debug, c) => ("", debug(c)),
+            (kind, Literal::Integer(n)) => {
+                let m = n.unsigned_abs();
+                let body = match kind {
+                    Kind::Hex => format!("{m:x}"),
+                    Kind::UpperHex => format!("{m:X}"),
+                    Kind::Octal => format!("{m:o}"),
+                    Kind::Binary => format!("{m:b}"),
+                    _ => m.to_string(),
+                };
+
+                (if *n < 0 { "-" } else { "" }, body)
+            }
+            (Kind::Display, c) => ("", c.to_string()),
+            (_, c) => {
+                return Err(format!(
+                    "only an `int` can be written in another radix, not `{}`",
+                    c.type_of()
+                )
+                .t(span))
+            }
+        };
+
+        let len = sign.chars().count() + body.chars().count();
+        let pad = self.width.saturating_sub(len);
+        let zero = self.zero && matches!(value, Literal::Integer(_)) && self.align.is_none();
+        let fill_len = if zero { 1 } else { self.fill.len_utf8() };
+
+        meter.size("str", sign.len() + body.len() + pad * fill_len, span)?;
+
+        if zero {
+            return Ok(format!("{sign}{}{body}", "0".repeat(pad)));
+        }
+
+        let text = sign.to_owned() + &body;
+        let fill = |n: usize| self.fill.to_string().repeat(n);
+        let align = self.align.unwrap_or(match value {
+            Literal::Integer(_) => Align::Right,
+            _ => Align::Left,
+        });
+
+        Ok(match align {
+            Align::Left => text + &fill(pad),
+            Align::Right => fill(pad) + &text,
+            Align::Center => fill(pad / 2) + &text + &fill(pad - pad / 2),
+        })
+    }
+}
+
+/// Like `Display`, except that strings are quoted and escaped, however deep they are.
+#[must_use]
+pub fn debug(value: &Literal<'_>) -> String {
+    debug_in(value, &mut vec![])
+}
+
+/// `s` in quotes, with quotes, backslashes and control characters escaped.
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('\'');
+
+    for c in s.chars() {
+        match c {
+            '\'' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c if c.is_control() => quoted.extend(c.escape_default()),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('\'');
+    quoted
+}
+
+/// [`debug`], inside of the values in `outer`. Those show up as `...`, as with `Display`, so that a
+/// value that contains itself ends.
+fn debug_in(value: &Literal<'_>, outer: &mut Vec<usize>) -> String {
+    let id = match value {
+        Literal::Env(scope) => Rc::as_ptr(scope) as usize,
+        _ => std::ptr::from_ref(value) as usize,
+    };
+
+    if outer.contains(&id) {
+        return "...".to_owned();
+    }
+
+    outer.push(id);
+
+    let mut items = |values: &[Rc<RefCell<Literal<'_>>>]| {
+        values
+            .iter()
+            .map(|x| debug_in(&x.borrow(), outer))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let text = match value {
+        Literal::String(s) => quote(s),
+        Literal::Array(values, _) => format!("[{}]", items(values)),
+        Literal::Set(values) => format!("set {{ {} }}", items(values)),
+        Literal::Object(fields, _) => format!(
+            "object {{ {} }}",
+            debug_fields(fields.iter().map(|(k, v)| (&**k, v)), outer)
+        ),
+        Literal::Env(scope) => format!(
+            "env {{ {} }}",
+            debug_fields(scope.borrow().iter().map(|(k, v)| (k.as_str(), v)), outer)
+        ),
+        c => c.to_string(),
+    };
+
+    outer.pop();
+    text
+}
+
+/// `a: x, b: y` with every value in [`debug`] form, sorted by name.
+fn debug_fields<'k, 'a: 'k>(
+    fields: impl Iterator<Item = (&'k str, &'k Rc<RefCell<Literal<'a>>>)>,
+    outer: &mut Vec<usize>,
+) -> String {
+    let mut fields = fields
+        .map(|(k, v)| format!("{k}: {}", debug_in(&v.borrow(), outer)))
+        .collect::<Vec<_>>();
+
+    fields.sort();
+    fields.join(", ")
+}
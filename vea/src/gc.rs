@@ -0,0 +1,323 @@
+//! Frees values that refer to each other in a cycle, which reference counting alone never does.
+//!
+//! The collector never needs to know what the Rust side holds on to: whatever is referred to more
+//! often than the heap itself accounts for is kept, along with everything it refers to.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    mem,
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    env::{Env, Scope},
+    literal::Literal,
+};
+
+/// How many values are tracked before the collector first runs on its own.
+const THRESHOLD: usize = 1024;
+
+type Value<'a> = Rc<RefCell<Literal<'a>>>;
+
+/// The values of a program that could end up in a cycle, shared by every scope of it.
+#[derive(Debug)]
+pub struct Heap<'a> {
+    tracked: RefCell<Vec<Weak<RefCell<Literal<'a>>>>>,
+    /// How many values are tracked when the collector next runs on its own.
+    next: Cell<usize>,
+    collections: Cell<u64>,
+    freed: Cell<u64>,
+}
+
+impl Default for Heap<'_> {
+    fn default() -> Self {
+        Self {
+            tracked: RefCell::default(),
+            next: Cell::new(THRESHOLD),
+            collections: Cell::default(),
+            freed: Cell::default(),
+        }
+    }
+}
+
+/// What [`Heap::stats`] reports.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct HeapStats {
+    /// How many values that could be part of a cycle are still alive.
+    pub live: usize,
+    /// How many times the collector has run.
+    pub collections: u64,
+    /// How many values it has freed, over all of those times.
+    pub freed: u64,
+}
+
+impl<'a> Heap<'a> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracks `value` if it was just made and can refer to other values. Runs the collector once
+    /// enough values have been tracked since it last ran.
+    pub fn track(&self, value: &Value<'a>) {
+        if Rc::strong_count(value) == 1 {
+            self.adopt(value);
+        }
+    }
+
+    /// Like [`Self::track`], for a value that was just made but is already referred to, like a copy
+    /// of a value that contains itself.
+    pub fn adopt(&self, value: &Value<'a>) {
+        if !value.try_borrow().is_ok_and(|x| refers(&x)) {
+            return;
+        }
+
+        let len = {
+            let mut tracked = self.tracked.borrow_mut();
+            tracked.push(Rc::downgrade(value));
+            tracked.len()
+        };
+
+        if len >= self.next.get() {
+            self.collect();
+        }
+    }
+
+    /// How many values are tracked, some of which may have been freed since.
+    #[must_use]
+    pub fn tracked(&self) -> usize {
+        self.tracked.borrow().len()
+    }
+
+    /// Forgets the tracked values that have been freed, and gives back how many are still alive.
+    pub fn prune(&self) -> usize {
+        let mut tracked = self.tracked.borrow_mut();
+        tracked.retain(|x| x.strong_count() > 0);
+        tracked.len()
+    }
+
+    #[must_use]
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            live: self
+                .tracked
+                .borrow()
+                .iter()
+                .filter(|x| x.strong_count() > 0)
+                .count(),
+            collections: self.collections.get(),
+            freed: self.freed.get(),
+        }
+    }
+
+    /// Frees every cycle that nothing outside of it refers to anymore, and gives back how many
+    /// values that was.
+    pub fn collect(&self) -> usize {
+        let mut graph = Graph::default();
+
+        let tracked = self
+            .tracked
+            .borrow()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect::<Vec<_>>();
+
+        for value in tracked {
+            graph.add(Node::Value(value));
+        }
+
+        let freed = graph.collect();
+
+        self.tracked.borrow_mut().retain(|x| x.strong_count() > 0);
+        let len = self.tracked.borrow().len();
+        self.next.set(THRESHOLD.max(len * 2));
+        self.collections.set(self.collections.get() + 1);
+        self.freed.set(self.freed.get() + freed as u64);
+
+        freed
+    }
+}
+
+/// Drops `env` and frees the cycles that only it kept alive, like those between its fns and the
+/// scope they were declared in. Gives back how many values that was.
+pub fn free(env: Rc<RefCell<Env<'_>>>) -> usize {
+    let heap = env.borrow().heap.clone();
+    drop(env);
+    heap.collect()
+}
+
+/// Whether `value` is of a type that can refer to other values.
+const fn refers(value: &Literal<'_>) -> bool {
+    matches!(
+        value,
+        Literal::Array(..)
+            | Literal::Set(_)
+            | Literal::Object(..)
+            | Literal::Env(_)
+            | Literal::Fn(..)
+    )
+}
+
+/// Something on the heap that can refer to other things on it.
+enum Node<'a> {
+    Value(Value<'a>),
+    Frame(Scope<'a>),
+    Env(Rc<RefCell<Env<'a>>>),
+}
+
+impl Node<'_> {
+    fn key(&self) -> *const () {
+        match self {
+            Self::Value(x) => Rc::as_ptr(x).cast(),
+            Self::Frame(x) => Rc::as_ptr(x).cast(),
+            Self::Env(x) => Rc::as_ptr(x).cast(),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Self::Value(x) => Rc::strong_count(x),
+            Self::Frame(x) => Rc::strong_count(x),
+            Self::Env(x) => Rc::strong_count(x),
+        }
+    }
+
+    /// What it refers to, or `None` if it is being changed and cannot be looked into.
+    fn children(&self) -> Option<Vec<Self>> {
+        Some(match self {
+            Self::Value(x) => match &*x.try_borrow().ok()? {
+                Literal::Array(x, _) | Literal::Set(x) => {
+                    x.iter().cloned().map(Self::Value).collect()
+                }
+                Literal::Object(fields, _) => fields.values().cloned().map(Self::Value).collect(),
+                Literal::Env(scope) => vec![Self::Frame(scope.clone())],
+                Literal::Fn(.., kind) => {
+                    let this = kind.this.iter().cloned().map(Self::Frame);
+                    this.chain(kind.scope.iter().cloned().map(Self::Env))
+                        .collect()
+                }
+                _ => vec![],
+            },
+            Self::Frame(x) => x
+                .try_borrow()
+                .ok()?
+                .iter()
+                .map(|(_, v)| Self::Value(v.clone()))
+                .collect(),
+            Self::Env(x) => {
+                let x = x.try_borrow().ok()?;
+                let mut children = vec![Self::Frame(x.values.clone())];
+                children.extend(x.this.iter().cloned().map(Self::Frame));
+                children.extend(x.parent.iter().cloned().map(Self::Env));
+                children
+            }
+        })
+    }
+
+    /// Drops everything that [`Self::children`] gives.
+    fn clear(&self) {
+        match self {
+            Self::Value(x) => {
+                if let Ok(mut x) = x.try_borrow_mut() {
+                    *x = Literal::None;
+                }
+            }
+            Self::Frame(x) => {
+                if let Ok(mut x) = x.try_borrow_mut() {
+                    mem::take(&mut *x);
+                }
+            }
+            Self::Env(x) => {
+                if let Ok(mut x) = x.try_borrow_mut() {
+                    x.values = Scope::default();
+                    x.this = None;
+                    x.parent = None;
+                }
+            }
+        }
+    }
+}
+
+/// Everything reachable from the tracked values, each held once, with what it refers to.
+#[derive(Default)]
+struct Graph<'a> {
+    nodes: Vec<Node<'a>>,
+    index: HashMap<*const (), usize>,
+    /// The nodes each node refers to, once for every reference, or `None` if it could not be
+    /// looked into.
+    edges: Vec<Option<Vec<usize>>>,
+}
+
+impl<'a> Graph<'a> {
+    /// Adds `node` and everything it refers to, if they are not there yet.
+    fn add(&mut self, node: Node<'a>) {
+        if self.index.contains_key(&node.key()) {
+            return;
+        }
+
+        let mut pending = vec![self.push(node)];
+
+        while let Some(i) = pending.pop() {
+            let edges = self.nodes[i].children().map(|children| {
+                children
+                    .into_iter()
+                    .map(|child| {
+                        if let Some(&j) = self.index.get(&child.key()) {
+                            return j;
+                        }
+
+                        let j = self.push(child);
+                        pending.push(j);
+                        j
+                    })
+                    .collect()
+            });
+
+            self.edges[i] = edges;
+        }
+    }
+
+    fn push(&mut self, node: Node<'a>) -> usize {
+        let i = self.nodes.len();
+        self.index.insert(node.key(), i);
+        self.nodes.push(node);
+        self.edges.push(Some(vec![]));
+        i
+    }
+
+    /// Frees the nodes that are only referred to by other nodes that are not reachable from
+    /// outside, and gives back how many of them were values.
+    fn collect(self) -> usize {
+        let mut internal = vec![0; self.nodes.len()];
+
+        for &j in self.edges.iter().flatten().flatten() {
+            internal[j] += 1;
+        }
+
+        // one reference to every node is the one held here
+        let mut pending = (0..self.nodes.len())
+            .filter(|&i| self.edges[i].is_none() || self.nodes[i].strong_count() > internal[i] + 1)
+            .collect::<Vec<_>>();
+
+        let mut reachable = vec![false; self.nodes.len()];
+
+        while let Some(i) = pending.pop() {
+            if mem::replace(&mut reachable[i], true) {
+                continue;
+            }
+
+            pending.extend(self.edges[i].iter().flatten());
+        }
+
+        let garbage = (0..self.nodes.len()).filter(|&i| !reachable[i]);
+        let mut freed = 0;
+
+        for i in garbage {
+            freed += usize::from(matches!(self.nodes[i], Node::Value(_)));
+            self.nodes[i].clear();
+        }
+
+        freed
+    }
+}
@@ -0,0 +1,81 @@
+//! Where `input()`, `read_line()` and `read_all()` read from.
+
+use std::{
+    fmt::Debug,
+    io::{self, BufRead, Read},
+};
+
+/// A source of input for a program, shared by every scope of it.
+pub trait Input: Debug {
+    /// The next line without its line ending, or `None` at the end of the input.
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+    /// Everything that has not been read yet.
+    fn read_all(&mut self) -> io::Result<String>;
+}
+
+/// Input from a fixed string, e.g. the text after a code block, or nothing at all.
+#[derive(Debug, Clone, Default)]
+pub struct Text {
+    text: String,
+    at: usize,
+}
+
+impl Text {
+    #[must_use]
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            at: 0,
+        }
+    }
+}
+
+impl Input for Text {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let rest = &self.text[self.at..];
+
+        if rest.is_empty() {
+            return Ok(None);
+        }
+
+        let line = rest.split_inclusive('\n').next().unwrap_or_default();
+        self.at += line.len();
+
+        Ok(Some(trim_newline(line).to_owned()))
+    }
+
+    fn read_all(&mut self) -> io::Result<String> {
+        let rest = self.text[self.at..].to_owned();
+        self.at = self.text.len();
+
+        Ok(rest)
+    }
+}
+
+/// Input from the standard input of this process.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stdin;
+
+impl Input for Stdin {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(trim_newline(&line).to_owned()))
+    }
+
+    fn read_all(&mut self) -> io::Result<String> {
+        let mut all = String::new();
+        io::stdin().lock().read_to_string(&mut all)?;
+
+        Ok(all)
+    }
+}
+
+fn trim_newline(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
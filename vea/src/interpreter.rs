@@ -1,7 +1,25 @@
 use chumsky::span::SimpleSpan;
 
-use crate::{ast::Expr, common::Tag, env::Env, literal::Literal, rc_cell, span::Span};
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use crate::{
+    ast::{Expr, Pattern},
+    common::{grow, Tag},
+    compiler::is_plain,
+    env::Env,
+    format::Spec,
+    lexer::Token,
+    literal::{Arg, FnKind, Literal, TotalOrdering},
+    module::load,
+    rc_cell,
+    resolver::Slot,
+    span::{RawSpan, Span},
+    symbol::Symbol,
+    vm::{self, Backend},
+};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 #[must_use]
 #[inline]
@@ -9,10 +27,27 @@ pub fn none<'a>() -> Rc<RefCell<Literal<'a>>> {
     Rc::new(RefCell::new(Literal::None))
 }
 
+/// Evaluates one expression, and checks what it made against the limits of the program.
 pub fn interp<'a>(
     program: &mut Env<'a>,
-    one: Span<Expr<'a>>,
+    one: Span<Expr>,
 ) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    let span = one.1;
+
+    let value = grow(|| eval(program, one))?;
+    program.meter.value(&value, span)?;
+    program.heap.track(&value);
+    program.meter.live(&program.heap, span)?;
+
+    Ok(value)
+}
+
+fn eval<'a>(
+    program: &mut Env<'a>,
+    one: Span<Expr>,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    use TotalOrdering::{AlmostEqual, Equal, GreaterThan, LessThan};
+
     let Span(one, full_span) = one;
 
     macro_rules! mm {
@@ -23,16 +58,11 @@ pub fn interp<'a>(
         };
 
         (* $t:tt $program:ident, $full_span:ident, $ident:ident, $expr:ident) => {{
-            let lhs = program.get(&$ident.0.to_string());
-
-            if let Some(lh) = lhs {
-                let lhv = lh.borrow().clone();
-                let rhv = interp(program, *$expr)?;
-                let out = (lhv $t rhv.borrow().clone()).map_err(|x| x.t($full_span))?;
-                program.set(&$ident.0.to_string(), Rc::new(RefCell::new(out)));
-            } else {
-                return Err(format!("variable `{}` does not exist", $ident.0).t($ident.1));
-            }
+            let at = slot($program, &$ident);
+            let lhv = $program.load(at, &$ident.0).map_err(|x| x.t($ident.1))?.borrow().clone();
+            let rhv = interp($program, *$expr)?;
+            let out = (lhv $t rhv.borrow().clone()).map_err(|x| x.t($full_span))?;
+            set($program, &$ident, at, Rc::new(RefCell::new(out)), $full_span)?;
 
             Ok(none())
         }};
@@ -42,7 +72,31 @@ pub fn interp<'a>(
             let rhv = interp($program, *$rhs)?.borrow().clone();
 
             lhv.$id(rhv).map(|x| Rc::new(RefCell::new(x))).map_err(|x| x.t($full_span))
-        }}
+        }};
+
+        (< $id:ident, $ok:pat, $program:ident, $full_span:ident, $lhs:ident, $rhs:ident) => {{
+            let lhv = interp($program, *$lhs)?;
+            let rhv = interp($program, *$rhs)?;
+
+            if method(&lhv, "cmp").is_some() {
+                let o = compare($program, &lhv, &rhv, $full_span)?;
+                return Ok(rc_cell!(Literal::Bool(matches!(o, $ok))));
+            }
+
+            let out = lhv.borrow().$id(&rhv.borrow());
+            out.map(|x| Rc::new(RefCell::new(x))).map_err(|x| x.t($full_span))
+        }};
+
+        (~ $program:ident, $full_span:ident, $lhs:ident, $epsilon:ident, $rhs:ident, $ok:pat) => {{
+            let lhv = interp($program, *$lhs)?.borrow().clone();
+            let eps = interp($program, *$epsilon)?.borrow().clone();
+            let rhv = interp($program, *$rhs)?.borrow().clone();
+
+            match lhv.rcmp_within(eps, rhv).map_err(|x| x.t($full_span))? {
+                Literal::Ordering(o) => Ok(rc_cell!(Literal::Bool(matches!(o, $ok)))),
+                _ => unreachable!(),
+            }
+        }};
     }
 
     // current branch is GONE
@@ -53,42 +107,238 @@ pub fn interp<'a>(
     }
 
     match one {
-        Expr::Literal { value } => Ok(Rc::new(RefCell::new(value))),
-        Expr::Access { ident } => {
-            let value = program.get(ident.0);
-            value.ok_or_else(|| format!("variable `{}` does not exist", ident.0).t(ident.1))
-        }
+        Expr::Literal { value } => Ok(Rc::new(RefCell::new(value.into()))),
+        Expr::Access { ident } => program
+            .load(slot(program, &ident), &ident.0)
+            .map_err(|x| x.t(ident.1)),
 
         Expr::Let { ident, expr, .. } => {
-            if program.has(ident.0) {
+            if program.has(&ident.0) {
                 return Err(format!("variable `{}` already exists", ident.0).t(ident.1));
             }
 
+            if let Expr::Restrict { expr, pred, .. } = expr.0 {
+                let value = interp(program, *expr)?;
+
+                if !restrict(program, &value, &pred)? {
+                    return Err(format!(
+                        "value {} does not satisfy the restriction on `{}`",
+                        value.borrow(),
+                        ident.0
+                    )
+                    .t(full_span));
+                }
+
+                program
+                    .assign(&ident.0, slot(program, &ident), value)
+                    .map_err(|x| x.t(full_span))?;
+                program.refine(&ident.0, *pred);
+
+                return Ok(none());
+            }
+
             let value = interp(program, *expr)?;
 
             program
-                .assign(ident.0, value)
-                .map(|_| none())
+                .assign(&ident.0, slot(program, &ident), value)
+                .map(|()| none())
                 .map_err(|x| x.t(full_span))
         }
 
-        Expr::Print { value, .. } => {
-            let val = interp(program, *value)?;
-            program.print(&val.borrow().to_string());
-            Ok(none())
+        Expr::Print {
+            print_token,
+            values,
+            ..
+        } => {
+            let mut text = vec![];
+
+            for value in values {
+                text.push(interp(program, value)?.borrow().to_string());
+            }
+
+            let mut text = text.join(" ");
+
+            if print_token.0 == Token::Println {
+                text += "\n";
+            }
+
+            print(program, &text, full_span).map(|()| none())
+        }
+
+        Expr::Interp { start, holes } => {
+            let mut out = text(&start.0);
+
+            for (expr, spec, after) in holes {
+                let span = spec.as_ref().map_or(expr.1, |x| x.1);
+                let value = interp(program, expr)?;
+
+                let spec = match spec {
+                    Some(Span(Token::FormatSpec(x), _)) => {
+                        Spec::parse(&x).map_err(|x| x.t(span))?
+                    }
+                    _ => Spec::default(),
+                };
+
+                out += &spec.format(&value.borrow(), &program.meter, span)?;
+                out += &text(&after.0);
+            }
+
+            Ok(rc_cell!(Literal::String(out)))
         }
 
         Expr::Assign { ident, expr, .. } => {
-            if !program.has(ident.0) {
+            let at = slot(program, &ident);
+
+            if !program.exists(at).map_err(|x| x.t(ident.1))? {
                 return Err(format!("variable `{}` does not exist", ident.0).t(ident.1));
             }
 
             let value = interp(program, *expr)?;
-            program.set(ident.0, value);
+            set(program, &ident, at, value, full_span)?;
 
             Ok(none())
         }
 
+        Expr::IndexAssign {
+            target,
+            eq_token,
+            expr,
+            ..
+        } => {
+            let Expr::Chain { parent, child } = target.0 else {
+                return Err("can only assign to an index".to_string().t(target.1));
+            };
+
+            let root = match root(&parent.0) {
+                Some(ident) => program
+                    .is_locked_at(slot(program, &ident), &ident.0)
+                    .map_err(|x| x.t(ident.1))?
+                    .then_some(ident.0),
+                None => None,
+            };
+            let object = interp(program, *parent)?;
+
+            if let Some(root) = root {
+                // an env keeps its unlocked properties mutable
+                if !matches!(*object.borrow(), Literal::Env(_)) {
+                    return Err(
+                        format!("cannot assign to `{root}` because it is locked").t(target.1)
+                    );
+                }
+            }
+
+            let key = interp(program, *child.clone())?.borrow().clone();
+            let mut value = interp(program, *expr)?;
+
+            if eq_token.0 != Token::Eq {
+                let old = index_into(&object.borrow(), key.clone(), child.1)?
+                    .borrow()
+                    .clone();
+                let out = compound(&eq_token.0, old, value.borrow().clone())
+                    .map_err(|x| x.t(full_span))?;
+                value = rc_cell!(out);
+            }
+
+            let mut object = object.borrow_mut();
+
+            match (&mut *object, key) {
+                (Literal::Object(fields, locked), Literal::String(s)) => {
+                    let Some(k) = fields.get_key_value(&*s).map(|(k, _)| k.clone()) else {
+                        return Err(format!("value does not have an index `{s}`").t(child.1));
+                    };
+
+                    if locked.contains(&k) {
+                        return Err(
+                            format!("cannot assign to field `{k}` because it is locked").t(child.1)
+                        );
+                    }
+
+                    fields.insert(k, value);
+                    Ok(none())
+                }
+                (Literal::Array(values, locked), Literal::Integer(i)) => {
+                    // the array itself is locked, e.g. through another variable
+                    if *locked {
+                        return Err("cannot assign into a locked array".to_string().t(target.1));
+                    }
+
+                    let i = index(values.len(), i).map_err(|x| x.t(child.1))?;
+                    values[i] = value;
+                    Ok(none())
+                }
+                (Literal::Env(values), Literal::String(s)) => {
+                    let mut values = values.borrow_mut();
+
+                    let Some(field) = values.get_mut(&s) else {
+                        return Err(format!("value does not have an index `{s}`").t(child.1));
+                    };
+
+                    *field = value;
+                    Ok(none())
+                }
+                (Literal::Object(..) | Literal::Array(..) | Literal::Env(..), c) => {
+                    Err(format!("cannot index with a value of type `{}`", c.type_of()).t(child.1))
+                }
+                (c, _) => Err(
+                    format!("cannot index into a value with type `{}`", c.type_of()).t(target.1),
+                ),
+            }
+        }
+
+        Expr::Use { path, .. } => {
+            let module = load(program, &path, full_span)?;
+            let name = path.last().unwrap();
+
+            program
+                .assign(&name.0, slot(program, name), module)
+                .map(|()| none())
+                .map_err(|x| x.t(full_span))
+        }
+
+        Expr::Lock { target, .. } => match target.0 {
+            Expr::Access { ident } => {
+                let at = slot(program, &ident);
+
+                program.lock_at(at, &ident.0).map_err(|x| x.t(ident.1))?;
+                Literal::lock(&program.load(at, &ident.0).map_err(|x| x.t(ident.1))?);
+
+                Ok(none())
+            }
+            Expr::Chain { parent, child } => {
+                let object = interp(program, *parent)?;
+                let key = interp(program, *child.clone())?.borrow().clone();
+
+                let mut object = object.borrow_mut();
+
+                match (&mut *object, key) {
+                    (Literal::Object(fields, locked), Literal::String(s)) => {
+                        let Some((k, field)) = fields.get_key_value(&*s) else {
+                            return Err(format!("value does not have an index `{s}`").t(child.1));
+                        };
+
+                        let (k, field) = (k.clone(), field.clone());
+                        locked.insert(k);
+                        drop(object);
+
+                        Literal::lock(&field);
+                        Ok(none())
+                    }
+                    (Literal::Object(..), c) => Err(format!(
+                        "cannot index with a value of type `{}`",
+                        c.type_of()
+                    )
+                    .t(child.1)),
+                    (c, _) => Err(
+                        format!("cannot index into a value with type `{}`", c.type_of())
+                            .t(target.1),
+                    ),
+                }
+            }
+            _ => Err("can only lock a variable or a field"
+                .to_string()
+                .t(target.1)),
+        },
+
         Expr::Add { lhs, rhs, .. } => mm! { + program, full_span, lhs, rhs },
         Expr::Sub { lhs, rhs, .. } => mm! { - program, full_span, lhs, rhs },
         Expr::Mul { lhs, rhs, .. } => mm! { * program, full_span, lhs, rhs },
@@ -113,10 +363,103 @@ pub fn interp<'a>(
 
         Expr::Eq { lhs, rhs, .. } => mm! { = req, program, full_span, lhs, rhs },
         Expr::Ne { lhs, rhs, .. } => mm! { = rne, program, full_span, lhs, rhs },
-        Expr::Gt { lhs, rhs, .. } => mm! { = rgt, program, full_span, lhs, rhs },
-        Expr::Ge { lhs, rhs, .. } => mm! { = rge, program, full_span, lhs, rhs },
-        Expr::Lt { lhs, rhs, .. } => mm! { = rlt, program, full_span, lhs, rhs },
-        Expr::Le { lhs, rhs, .. } => mm! { = rle, program, full_span, lhs, rhs },
+        Expr::Gt { lhs, rhs, .. } => mm! { < rgt, GreaterThan, program, full_span, lhs, rhs },
+        Expr::Ge { lhs, rhs, .. } => {
+            mm! { < rge, GreaterThan | Equal, program, full_span, lhs, rhs }
+        }
+        Expr::Lt { lhs, rhs, .. } => mm! { < rlt, LessThan, program, full_span, lhs, rhs },
+        Expr::Le { lhs, rhs, .. } => mm! { < rle, LessThan | Equal, program, full_span, lhs, rhs },
+
+        Expr::Slice {
+            parent,
+            start,
+            end,
+            inclusive,
+        } => {
+            let value = interp(program, *parent.clone())?.borrow().clone();
+
+            let len = match &value {
+                Literal::String(s) => s.chars().count(),
+                Literal::Array(v, _) => v.len(),
+                c => {
+                    return Err(
+                        format!("cannot slice a value with type `{}`", c.type_of()).t(parent.1)
+                    )
+                }
+            };
+
+            let mut bound = |x: Option<Box<Span<Expr>>>, default: usize, end: bool| {
+                let Some(x) = x else {
+                    return Ok(default);
+                };
+
+                let span = x.1;
+
+                match *interp(program, *x)?.borrow() {
+                    Literal::Integer(i) => usize::try_from(i)
+                        .ok()
+                        .map(|i| if end && inclusive { i + 1 } else { i })
+                        .filter(|&i| i <= len)
+                        .ok_or_else(|| {
+                            format!("index {i} is out of bounds for a length of {len}").t(span)
+                        }),
+                    ref c => {
+                        Err(format!("cannot slice with a value of type `{}`", c.type_of()).t(span))
+                    }
+                }
+            };
+
+            let start = bound(start, 0, false)?;
+            let end = bound(end, len, true)?;
+
+            if start > end {
+                return Err(format!("slice starts at {start} but ends at {end}").t(full_span));
+            }
+
+            Ok(rc_cell!(match value {
+                Literal::String(s) =>
+                    Literal::String(s.chars().skip(start).take(end - start).collect()),
+                Literal::Array(v, _) => Literal::Array(v[start..end].to_vec(), false),
+                _ => unreachable!(),
+            }))
+        }
+
+        Expr::Restrict { expr, pred, .. } => {
+            let value = interp(program, *expr)?;
+
+            if restrict(program, &value, &pred)? {
+                Ok(value)
+            } else {
+                Ok(none())
+            }
+        }
+
+        Expr::Is { lhs, pattern, .. } => {
+            let value = interp(program, *lhs)?;
+            is(program, &value, &pattern).map(|x| rc_cell!(Literal::Bool(x)))
+        }
+
+        Expr::AlmostGt {
+            lhs, epsilon, rhs, ..
+        } => mm! { ~ program, full_span, lhs, epsilon, rhs, GreaterThan },
+        Expr::AlmostGe {
+            lhs, epsilon, rhs, ..
+        } => {
+            mm! { ~ program, full_span, lhs, epsilon, rhs, GreaterThan | Equal | AlmostEqual }
+        }
+        Expr::AlmostLt {
+            lhs, epsilon, rhs, ..
+        } => mm! { ~ program, full_span, lhs, epsilon, rhs, LessThan },
+        Expr::AlmostLe {
+            lhs, epsilon, rhs, ..
+        } => {
+            mm! { ~ program, full_span, lhs, epsilon, rhs, LessThan | Equal | AlmostEqual }
+        }
+        Expr::AlmostEq {
+            lhs, epsilon, rhs, ..
+        } => {
+            mm! { ~ program, full_span, lhs, epsilon, rhs, Equal | AlmostEqual }
+        }
 
         Expr::Neg { expr, .. } => (-interp(program, *expr)?.borrow().clone())
             .map_err(|x| x.t(full_span))
@@ -126,14 +469,49 @@ pub fn interp<'a>(
             .map_err(|x| x.t(full_span))
             .map(|x| Rc::new(RefCell::new(x))),
 
-        Expr::Block { exprs, .. } => {
-            exec(
-                exprs,
-                &Rc::new(RefCell::new(Env::with_parent(
-                    None,
-                    Rc::new(RefCell::new(program.clone())),
-                ))),
-            )?;
+        Expr::Copy { expr, .. } => {
+            let value = interp(program, *expr)?;
+            Ok(Literal::deep_copy(&value, &program.heap))
+        }
+
+        Expr::Block { exprs, .. } => scope(program, exprs, false),
+
+        Expr::Unsafe { block, .. } => {
+            let Expr::Block { exprs, .. } = block.0 else {
+                return Err("`unsafe` must be followed by a block"
+                    .to_string()
+                    .t(block.1));
+            };
+
+            scope(program, exprs, true)
+        }
+
+        Expr::Empty { .. } => {
+            if program.is_unsafe {
+                Ok(none())
+            } else {
+                Err("`_` can only be used inside an `unsafe` block"
+                    .to_string()
+                    .t(full_span))
+            }
+        }
+
+        // methods of objects, like `fn cmp(self, other)`, take `self` as an argument
+        Expr::This { .. } => match program.get("self") {
+            Some(value) => Ok(value),
+            None => program
+                .this
+                .clone()
+                .map(|x| rc_cell!(Literal::Env(x)))
+                .ok_or_else(|| {
+                    "`self` can only be used inside a fn"
+                        .to_string()
+                        .t(full_span)
+                }),
+        },
+
+        Expr::Statement { expr, .. } => {
+            interp(program, *expr)?;
             Ok(none())
         }
 
@@ -143,72 +521,97 @@ pub fn interp<'a>(
         Expr::FnCall {
             access, arguments, ..
         } => {
-            let value = interp(program, *access.clone())?.borrow().clone();
-
-            if let Literal::Fn(name, argv, bloc) = value {
-                let local = Rc::new(RefCell::new(Env::with_parent(
-                    Some(name.0.to_string()),
-                    Rc::new(RefCell::new(program.clone())),
-                )));
+            let (value, args) = callee(program, *access, arguments, full_span)?;
 
-                if arguments.len() > argv.len() {
-                    return Err(format!(
-                        "fn `{}` expected {} arguments but got {}",
-                        name.0,
-                        argv.len(),
-                        arguments.len()
-                    )
-                    .t(arguments
-                        .get(argv.len())
-                        .map(|x| SimpleSpan::new(x.1.start, arguments.last().unwrap().1.end))
-                        .unwrap()));
-                }
-
-                for (i, arg) in argv.iter().enumerate() {
-                    let argr = arguments.get(i);
-
-                    if argr.is_none() {
-                        return Err(format!(
-                            "fn `{}` expected {} arguments but got {}",
-                            name.0,
-                            argv.len(),
-                            arguments.len()
-                        )
-                        .t(name.1));
-                    }
+            if let Literal::NativeFn(_, _, f) = &value {
+                return (f.0)(program, &args, full_span);
+            }
 
-                    let actual = interp(program, argr.unwrap().clone())?;
+            call(
+                program,
+                &value,
+                args.into_iter().map(|x| x.0).collect(),
+                full_span,
+            )
+        }
 
-                    local.borrow_mut().set(arg.0, actual);
-                }
+        Expr::FnDecl {
+            unsafe_token,
+            test_token,
+            name,
+            arguments,
+            block,
+            ..
+        } => {
+            let kind = FnKind {
+                this: None,
+                is_unsafe: unsafe_token.is_some(),
+                unsafe_body: program.is_unsafe,
+                is_test: test_token.is_some(),
+                scope: Some(Rc::new(RefCell::new(program.clone()))),
+            };
 
-                if let Expr::Block { exprs, .. } = bloc.0 {
-                    exec(exprs, &local)?;
+            let value = rc_cell!(Literal::Fn(name.clone(), arguments, block, kind));
+            program.heap.track(&value);
 
-                    if !local.borrow().retyet {
-                        return Err(format!("fn `{}` doesn't return anything", &name.0).t(name.1));
-                    }
-                } else {
-                    return Err(format!("fn `{}` has a magic non-block body", &name.0).t(bloc.1));
-                }
-                Ok(program.get_ret(name.0).unwrap_or_else(none))
-            } else {
-                Err("value is not a function".to_string().t(access.1))
-            }
+            program
+                .assign(&name.0, slot(program, &name), value)
+                .map(|()| none())
+                .map_err(|x| x.t(full_span))
         }
 
-        Expr::FnDecl {
+        Expr::Method {
+            unsafe_token,
             name,
             arguments,
             block,
             ..
-        } => program
-            .assign(
-                name.0,
-                Rc::new(RefCell::new(Literal::Fn(name, arguments, block))),
-            )
-            .map(|_| none())
-            .map_err(|x| x.t(full_span)),
+        } => {
+            let Some(this) = program.this.clone() else {
+                return Err("methods can only be declared inside a fn"
+                    .to_string()
+                    .t(full_span));
+            };
+
+            let kind = FnKind {
+                this: Some(this.clone()),
+                is_unsafe: unsafe_token.is_some(),
+                unsafe_body: program.is_unsafe,
+                is_test: false,
+                scope: Some(Rc::new(RefCell::new(program.clone()))),
+            };
+
+            if this.borrow().contains_key(&name.0) {
+                return Err(format!("`self` already has a field `{}`", name.0).t(name.1));
+            }
+
+            let value = rc_cell!(Literal::Fn(name.clone(), arguments, block, kind));
+            program.heap.track(&value);
+            this.borrow_mut()
+                .declare(slot(program, &name), &name.0, value)
+                .map_err(|x| x.t(name.1))?;
+
+            Ok(none())
+        }
+
+        Expr::LetSelf { ident, expr, .. } => {
+            let Some(this) = program.this.clone() else {
+                return Err("`self` can only be used inside a fn"
+                    .to_string()
+                    .t(full_span));
+            };
+
+            if this.borrow().contains_key(&ident.0) {
+                return Err(format!("`self` already has a field `{}`", ident.0).t(ident.1));
+            }
+
+            let value = interp(program, *expr)?;
+            this.borrow_mut()
+                .declare(slot(program, &ident), &ident.0, value)
+                .map_err(|x| x.t(ident.1))?;
+
+            Ok(none())
+        }
 
         Expr::If {
             condition,
@@ -239,11 +642,19 @@ pub fn interp<'a>(
             condition, then, ..
         } => {
             loop {
+                program.interrupt.check(full_span)?;
                 let cond = interp(program, *condition.clone())?.borrow().clone();
 
                 if let Literal::Bool(b) = cond {
                     if b {
                         interp(program, *then.clone())?;
+
+                        if program.retyet {
+                            break;
+                        }
+
+                        // back to the condition, as the VM jumps back to it
+                        program.meter.step(full_span)?;
                     } else {
                         break;
                     }
@@ -257,13 +668,42 @@ pub fn interp<'a>(
         }
 
         Expr::Return { value, .. } => {
+            if let (Some(name), Some(tail), Expr::FnCall { .. }) =
+                (program.name.clone(), program.tail.clone(), &value.0)
+            {
+                let Span(
+                    Expr::FnCall {
+                        access, arguments, ..
+                    },
+                    span,
+                ) = *value
+                else {
+                    unreachable!()
+                };
+                let (f, args) = callee(program, *access, arguments, span)?;
+
+                let v = if let Literal::NativeFn(_, _, native) = &f {
+                    (native.0)(program, &args, span)?
+                } else {
+                    *tail.borrow_mut() = Some(TailCall {
+                        f,
+                        args: args.into_iter().map(|x| x.0).collect(),
+                        span,
+                        is_unsafe: program.is_unsafe,
+                    });
+                    none()
+                };
+
+                program.retyet = true;
+                program.set_ret(&name, v);
+                return Ok(none());
+            }
+
             let v = interp(program, *value)?;
 
-            if let Some(mut parent) = program.parent.as_ref().map(|x| x.borrow_mut()) {
-                println!("return {:?} within {:?}", v, &program.name);
+            if let Some(name) = program.name.clone() {
                 program.retyet = true;
-                let name = program.name.as_ref().cloned().unwrap();
-                parent.set_ret(&name, v);
+                program.set_ret(&name, v);
                 Ok(none())
             } else {
                 Err("used `return` statement outside of a `fn` block"
@@ -281,21 +721,40 @@ pub fn interp<'a>(
                         obj.insert(ident.0, interp(program, *expr)?);
                     }
                     Expr::FnDecl {
+                        unsafe_token,
                         name,
                         arguments,
                         block,
                         ..
                     } => {
+                        let kind = FnKind {
+                            this: None,
+                            is_unsafe: unsafe_token.is_some(),
+                            unsafe_body: program.is_unsafe,
+                            is_test: false,
+                            scope: Some(Rc::new(RefCell::new(program.clone()))),
+                        };
+
                         obj.insert(
-                            name.0,
-                            Rc::new(RefCell::new(Literal::Fn(name, arguments, block))),
+                            name.0.clone(),
+                            Rc::new(RefCell::new(Literal::Fn(name, arguments, block, kind))),
                         );
                     }
                     _ => return Err("unreachable state".to_string().t(s)),
                 }
             }
 
-            Ok(rc_cell!(Literal::Object(obj)))
+            Ok(rc_cell!(Literal::Object(obj, HashSet::new())))
+        }
+
+        Expr::Array { exprs, .. } => {
+            let mut array = vec![];
+
+            for expr in exprs {
+                array.push(interp(program, expr)?);
+            }
+
+            Ok(rc_cell!(Literal::Array(array, false)))
         }
 
         Expr::Set { exprs, .. } => {
@@ -368,35 +827,606 @@ pub fn interp<'a>(
         Expr::Chain { parent, child } => {
             let p = interp(program, *parent.clone())?.borrow().clone();
 
-            match p {
-                Literal::Object(v) => {
-                    let c = interp(program, *child.clone())?.borrow().clone();
-                    match c {
-                        Literal::String(s) => {
-                            return v.get(&*s).cloned().ok_or_else(|| {
-                                format!("value does not have an index `{}`", s).t(child.1)
-                            })
-                        }
-                        c => Err(
-                            format!("cannot index with a value of type `{}`", c.type_of())
-                                .t(child.1),
-                        ),
-                    }
+            if !indexable(&p) {
+                return Err(
+                    format!("cannot index into a value with type `{}`", p.type_of()).t(parent.1),
+                );
+            }
+
+            let c = interp(program, *child.clone())?.borrow().clone();
+            index_into(&p, c, child.1)
+        }
+    }
+}
+
+/// The text of a piece of an interpolated string.
+#[must_use]
+pub fn text(token: &Token) -> String {
+    match token {
+        Token::InterpStart(x) | Token::InterpMid(x) | Token::InterpEnd(x) => x.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Orders two values, calling `fn cmp(self, other)` if `lhs` is an object that has one.
+pub fn compare<'a>(
+    program: &mut Env<'a>,
+    lhs: &Rc<RefCell<Literal<'a>>>,
+    rhs: &Rc<RefCell<Literal<'a>>>,
+    span: RawSpan,
+) -> Result<TotalOrdering, Span<String>> {
+    if let Some(hook) = method(lhs, "cmp") {
+        return match call(program, &hook, vec![lhs.clone(), rhs.clone()], span)?
+            .borrow()
+            .clone()
+        {
+            Literal::Ordering(o) => Ok(o),
+            c => Err(format!("fn `cmp` must return `ordering`, not `{}`", c.type_of()).t(span)),
+        };
+    }
+
+    let ordering = lhs.borrow().ordering(&rhs.borrow());
+    ordering.map(Into::into).map_err(|x| x.t(span))
+}
+
+/// Looks up a function stored in a field of an object, like `fn cmp(self, other)`.
+pub fn method<'a>(value: &Rc<RefCell<Literal<'a>>>, name: &str) -> Option<Literal<'a>> {
+    match &*value.borrow() {
+        Literal::Object(fields, ..) => fields
+            .get(name)
+            .map(|x| x.borrow().clone())
+            .filter(|x| matches!(x, Literal::Fn(..))),
+        _ => None,
+    }
+}
+
+/// Checks that `value` can be called with arguments at `args`, before any of them are evaluated.
+pub fn check_call(
+    value: &Literal<'_>,
+    args: &[RawSpan],
+    access: RawSpan,
+    span: RawSpan,
+) -> Result<(), Span<String>> {
+    let expected = |name: &str, arity: &dyn std::fmt::Display| {
+        format!(
+            "fn `{name}` expected {arity} arguments but got {}",
+            args.len()
+        )
+    };
+
+    match value {
+        Literal::NativeFn(name, arity, _) if !arity.accepts(args.len()) => {
+            Err(expected(name, arity).t(span))
+        }
+        Literal::Fn(name, argv, ..) if args.len() > argv.len() => {
+            Err(expected(&name.0, &argv.len()).t(SimpleSpan::new(
+                args[argv.len()].start,
+                args[args.len() - 1].end,
+            )))
+        }
+        Literal::Fn(name, argv, ..) if args.len() < argv.len() => {
+            Err(expected(&name.0, &argv.len()).t(name.1))
+        }
+        Literal::NativeFn(..) | Literal::Fn(..) => Ok(()),
+        _ => Err("value is not a function".to_string().t(access)),
+    }
+}
+
+/// Whether `parent[key]` can be used at all, before `key` is evaluated.
+#[must_use]
+pub const fn indexable(parent: &Literal<'_>) -> bool {
+    matches!(
+        parent,
+        Literal::Object(..) | Literal::String(_) | Literal::Env(_) | Literal::Array(..)
+    )
+}
+
+/// `parent[key]`, where `span` is the span of `key`.
+pub fn index_into<'a>(
+    parent: &Literal<'a>,
+    key: Literal<'a>,
+    span: RawSpan,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    match (parent, key) {
+        (Literal::Object(v, ..), Literal::String(s)) => v
+            .get(&*s)
+            .cloned()
+            .ok_or_else(|| format!("value does not have an index `{s}`").t(span)),
+        (Literal::String(v), Literal::Integer(i)) => {
+            let chars = v.chars().collect::<Vec<_>>();
+
+            index(chars.len(), i)
+                .map(|i| rc_cell!(Literal::String(chars[i].to_string())))
+                .map_err(|x| x.t(span))
+        }
+        (Literal::Env(v), Literal::String(s)) => v
+            .borrow()
+            .get(&s)
+            .cloned()
+            .ok_or_else(|| format!("value does not have an index `{s}`").t(span)),
+        (Literal::Array(v, _), Literal::Integer(i)) => index(v.len(), i)
+            .map(|i| v[i].clone())
+            .map_err(|x| x.t(span)),
+        (_, c) => Err(format!("cannot index with a value of type `{}`", c.type_of()).t(span)),
+    }
+}
+
+fn index(len: usize, i: i64) -> Result<usize, String> {
+    usize::try_from(i)
+        .ok()
+        .filter(|&x| x < len)
+        .ok_or_else(|| format!("index {i} is out of bounds for an array of length {len}"))
+}
+
+/// The variable that `a[b][c]` or `a::b::c` indexes into, if it starts with one.
+fn root(expr: &Expr) -> Option<Span<Symbol>> {
+    match expr {
+        Expr::Access { ident } => Some(ident.clone()),
+        Expr::Chain { parent, .. } => root(&parent.0),
+        _ => None,
+    }
+}
+
+/// `lhs op rhs`, for the `op=` of a compound assignment.
+fn compound<'a>(op: &Token, lhs: Literal<'a>, rhs: Literal<'a>) -> Result<Literal<'a>, String> {
+    match op {
+        Token::PlusEq => lhs + rhs,
+        Token::MinusEq => lhs - rhs,
+        Token::StarEq => lhs * rhs,
+        Token::SlashEq => lhs / rhs,
+        Token::PercentEq => lhs % rhs,
+        Token::AndEq => lhs & rhs,
+        Token::PipeEq => lhs | rhs,
+        Token::CaretEq => lhs ^ rhs,
+        Token::ShlEq => lhs << rhs,
+        Token::ShrEq => lhs >> rhs,
+        _ => Err(format!("`{op}` is not an assignment")),
+    }
+}
+
+/// Assigns to an existing variable, respecting its lock and restriction.
+pub fn set<'a>(
+    program: &mut Env<'a>,
+    ident: &Span<Symbol>,
+    at: Option<Slot>,
+    value: Rc<RefCell<Literal<'a>>>,
+    span: RawSpan,
+) -> Result<(), Span<String>> {
+    if let Some(pred) = program
+        .refinement_at(at, &ident.0)
+        .map_err(|x| x.t(ident.1))?
+    {
+        if !restrict(program, &value, &pred)? {
+            return Err(format!(
+                "value {} does not satisfy the restriction on `{}`",
+                value.borrow(),
+                ident.0
+            )
+            .t(span));
+        }
+    }
+
+    program
+        .set_at(at, &ident.0, value)
+        .map_err(|x| x.t(ident.1))
+}
+
+/// Where the resolver found the variable `ident`.
+fn slot(program: &Env<'_>, ident: &Span<Symbol>) -> Option<Slot> {
+    program.resolution.borrow().get(ident.1)
+}
+
+/// Evaluates the predicate of `x{pred}` with `it` bound to `value`. If the predicate is a function,
+/// it is called with `value` instead.
+pub fn restrict<'a>(
+    program: &mut Env<'a>,
+    value: &Rc<RefCell<Literal<'a>>>,
+    pred: &Span<Expr>,
+) -> Result<bool, Span<String>> {
+    let mut local = Env::with_parent(None, Rc::new(RefCell::new(program.clone())));
+    local.bind("it", value.clone());
+
+    let mut out = interp(&mut local, pred.clone())?.borrow().clone();
+
+    if let Literal::Fn(..) | Literal::NativeFn(..) = out {
+        out = call(program, &out, vec![value.clone()], pred.1)?
+            .borrow()
+            .clone();
+    }
+
+    match out {
+        Literal::Bool(b) => Ok(b),
+        c => Err(format!("restriction must be of type `bool`, not `{}`", c.type_of()).t(pred.1)),
+    }
+}
+
+/// Tests `value` against `pattern`, as in `value?pattern`.
+pub fn is<'a>(
+    program: &mut Env<'a>,
+    value: &Rc<RefCell<Literal<'a>>>,
+    pattern: &Span<Pattern>,
+) -> Result<bool, Span<String>> {
+    let Span(pattern, span) = pattern;
+
+    match pattern {
+        Pattern::Wildcard => Ok(true),
+        Pattern::Literal { value: other } => is_value(program, value, other.clone().into(), *span),
+        Pattern::Range {
+            start,
+            end,
+            inclusive,
+        } => Ok(match *value.borrow() {
+            Literal::Integer(x) if *inclusive => (*start..=*end).contains(&x),
+            Literal::Integer(x) => (*start..*end).contains(&x),
+            _ => false,
+        }),
+        Pattern::Ident { ident } => {
+            if Literal::TYPES.contains(&&*ident.0) {
+                return Ok(*value.borrow().type_of() == *ident.0);
+            }
+
+            let other = program
+                .load(slot(program, ident), &ident.0)
+                .map_err(|x| x.t(ident.1))?;
+            let other = other.borrow().clone();
+
+            is_value(program, value, other, *span)
+        }
+        Pattern::Value { expr } => {
+            let other = interp(program, *expr.clone())?.borrow().clone();
+            is_value(program, value, other, *span)
+        }
+        Pattern::Object { fields } => {
+            let Literal::Object(values, ..) = value.borrow().clone() else {
+                return Ok(false);
+            };
+
+            for (k, p) in fields {
+                match values.get(&k.0) {
+                    Some(v) if is(program, v, p)? => {}
+                    _ => return Ok(false),
+                }
+            }
+
+            Ok(true)
+        }
+        Pattern::Set { items } => {
+            let Literal::Set(values) = value.borrow().clone() else {
+                return Ok(false);
+            };
+
+            if values.len() != items.len() {
+                return Ok(false);
+            }
+
+            // sets are unordered, so every item needs its own value
+            let mut matrix = vec![];
+
+            for v in &values {
+                let mut row = vec![];
+
+                for p in items {
+                    row.push(is(program, v, p)?);
+                }
+
+                matrix.push(row);
+            }
+
+            Ok(assign(&matrix, 0, &mut vec![false; items.len()]))
+        }
+        Pattern::Array { items } => {
+            let Literal::Array(values, _) = value.borrow().clone() else {
+                return Ok(false);
+            };
+
+            if values.len() != items.len() {
+                return Ok(false);
+            }
+
+            for (v, p) in values.iter().zip(items) {
+                if !is(program, v, p)? {
+                    return Ok(false);
                 }
-                c => Err(
-                    format!("cannot index into a value with type `{}`", c.type_of()).t(parent.1),
-                ),
             }
+
+            Ok(true)
+        }
+        Pattern::Or { alternatives } => {
+            for p in alternatives {
+                if is(program, value, p)? {
+                    return Ok(true);
+                }
+            }
+
+            Ok(false)
         }
     }
 }
 
+/// Values compare by equality, unless `value` is an object with its own `fn is(self, other)`.
+fn is_value<'a>(
+    program: &mut Env<'a>,
+    value: &Rc<RefCell<Literal<'a>>>,
+    other: Literal<'a>,
+    span: RawSpan,
+) -> Result<bool, Span<String>> {
+    if let Some(hook) = method(value, "is") {
+        return match call(program, &hook, vec![value.clone(), rc_cell!(other)], span)?
+            .borrow()
+            .clone()
+        {
+            Literal::Bool(b) => Ok(b),
+            c => Err(format!("fn `is` must return `bool`, not `{}`", c.type_of()).t(span)),
+        };
+    }
+
+    Ok(matches!(
+        value.borrow().clone().req(other),
+        Ok(Literal::Bool(true))
+    ))
+}
+
+fn assign(matrix: &[Vec<bool>], row: usize, taken: &mut Vec<bool>) -> bool {
+    if row == matrix.len() {
+        return true;
+    }
+
+    for (i, &ok) in matrix[row].iter().enumerate() {
+        if ok && !taken[i] {
+            taken[i] = true;
+
+            if assign(matrix, row + 1, taken) {
+                return true;
+            }
+
+            taken[i] = false;
+        }
+    }
+
+    false
+}
+
+/// Writes `text` to the output of the program.
+pub fn print(program: &Env<'_>, text: &str, span: RawSpan) -> Result<(), Span<String>> {
+    program
+        .print(text)
+        .map_err(|e| format!("cannot write output: {e}").t(span))
+}
+
+/// Evaluates what a call expression calls and its arguments, and checks that they fit.
+fn callee<'a>(
+    program: &mut Env<'a>,
+    access: Span<Expr>,
+    arguments: Vec<Span<Expr>>,
+    span: RawSpan,
+) -> Result<(Literal<'a>, Vec<Arg<'a>>), Span<String>> {
+    let access_span = access.1;
+    let value = interp(program, access)?.borrow().clone();
+    let spans = arguments.iter().map(|x| x.1).collect::<Vec<_>>();
+    check_call(&value, &spans, access_span, span)?;
+
+    let mut args = vec![];
+
+    for arg in arguments {
+        let span = arg.1;
+        args.push(Span(interp(program, arg)?, span));
+    }
+
+    Ok((value, args))
+}
+
+/// A call in tail position, like `return f(x);`, left for the [`call`] it returns from to make, so
+/// that it does not need a stack frame of its own.
+#[derive(Debug)]
+pub struct TailCall<'a> {
+    pub f: Literal<'a>,
+    pub args: Vec<Rc<RefCell<Literal<'a>>>>,
+    pub span: RawSpan,
+    /// Whether it was made from an `unsafe` scope.
+    pub is_unsafe: bool,
+}
+
+/// Where the body of a fn leaves its [`TailCall`].
+pub type Tail<'a> = Rc<RefCell<Option<TailCall<'a>>>>;
+
+/// Calls a function value with already evaluated arguments. `span` is blamed for arity and type
+/// errors, as there are no argument expressions to point at.
+pub fn call<'a>(
+    program: &mut Env<'a>,
+    f: &Literal<'a>,
+    args: Vec<Rc<RefCell<Literal<'a>>>>,
+    span: RawSpan,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    let tail = Rc::new(RefCell::new(None));
+    let mut out = call_once(program, program.is_unsafe, f, args, span, &tail);
+
+    // each tail call replaces the one that made it, instead of running inside of it
+    loop {
+        let next = tail.borrow_mut().take();
+
+        match (out, next) {
+            (Ok(_), Some(next)) => {
+                out = call_once(
+                    program,
+                    next.is_unsafe,
+                    &next.f,
+                    next.args,
+                    next.span,
+                    &tail,
+                );
+            }
+            (out, _) => return out,
+        }
+    }
+}
+
+fn call_once<'a>(
+    program: &mut Env<'a>,
+    is_unsafe: bool,
+    f: &Literal<'a>,
+    args: Vec<Rc<RefCell<Literal<'a>>>>,
+    span: RawSpan,
+    tail: &Tail<'a>,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    if let Literal::NativeFn(name, arity, f) = f {
+        if !arity.accepts(args.len()) {
+            return Err(format!(
+                "fn `{name}` expected {arity} arguments but got {}",
+                args.len()
+            )
+            .t(span));
+        }
+
+        let args = args.into_iter().map(|x| Span(x, span)).collect::<Vec<_>>();
+        return (f.0)(program, &args, span);
+    }
+
+    let Literal::Fn(name, argv, bloc, kind) = f else {
+        return Err(format!("value of type `{}` is not a function", f.type_of()).t(span));
+    };
+
+    if args.len() != argv.len() {
+        return Err(format!(
+            "fn `{}` expected {} arguments but got {}",
+            name.0,
+            argv.len(),
+            args.len()
+        )
+        .t(span));
+    }
+
+    if kind.is_unsafe && !is_unsafe {
+        return Err(format!(
+            "fn `{}` is unsafe, so it can only be called inside an `unsafe` block",
+            name.0
+        )
+        .t(span));
+    }
+
+    // the body sees the scope the fn was declared in, not the one it is called from
+    let mut parent = kind
+        .scope
+        .clone()
+        .unwrap_or_else(|| Rc::new(RefCell::new(program.clone())));
+
+    // methods see the fields of their `self` before anything else
+    if let Some(this) = &kind.this {
+        parent = Rc::new(RefCell::new(Env {
+            values: this.clone(),
+            ..Env::with_parent(None, parent)
+        }));
+    }
+
+    let local = Rc::new(RefCell::new(Env::with_parent(
+        Some(name.0.to_string()),
+        parent,
+    )));
+
+    {
+        let mut local = local.borrow_mut();
+        local.this = Some(kind.this.clone().unwrap_or_else(|| local.values.clone()));
+        local.is_unsafe = kind.is_unsafe || kind.unsafe_body;
+        local.tail = Some(tail.clone());
+
+        for (arg, actual) in argv.iter().zip(args) {
+            local.bind(&arg.0, actual);
+        }
+    }
+
+    let Expr::Block { exprs, .. } = &bloc.0 else {
+        return Err(format!("fn `{}` has a magic non-block body", &name.0).t(bloc.1));
+    };
+
+    program.interrupt.check(span)?;
+    program.meter.step(span)?;
+    program.meter.enter(span)?;
+    let result = grow(|| exec(exprs.clone(), &local));
+    program.meter.leave();
+    result?;
+
+    let local = local.borrow();
+
+    if !local.retyet {
+        // a fn that builds up `self` gives it back
+        if is_constructor(exprs) {
+            return Ok(rc_cell!(Literal::Env(local.values.clone())));
+        }
+
+        if kind.is_test {
+            return Ok(none());
+        }
+
+        return Err(format!("fn `{}` doesn't return anything", &name.0).t(name.1));
+    }
+
+    let value = local.retval.borrow().get(&*name.0).cloned();
+    Ok(value.unwrap_or_else(none))
+}
+
+/// Whether a fn body declares methods or fields of `self`, directly or in a nested block.
+fn is_constructor(exprs: &[Span<Expr>]) -> bool {
+    exprs.iter().any(|Span(expr, _)| match expr {
+        Expr::Method { .. } | Expr::LetSelf { .. } => true,
+        Expr::Block { exprs, .. } => is_constructor(exprs),
+        Expr::Unsafe { block, .. } => is_constructor(std::slice::from_ref(block)),
+        _ => false,
+    })
+}
+
+/// Runs a block in a child scope, unless nothing in it could declare a variable, the same as the
+/// VM and the resolver do. A `return` inside of it returns from the enclosing fn.
+fn scope<'a>(
+    program: &mut Env<'a>,
+    exprs: Vec<Span<Expr>>,
+    is_unsafe: bool,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    if !is_unsafe && exprs.iter().all(|x| is_plain(&x.0)) {
+        for expr in exprs {
+            let returns = matches!(expr.0, Expr::Return { .. });
+            interp(program, expr)?;
+
+            if program.retyet && !returns {
+                break;
+            }
+        }
+
+        return Ok(none());
+    }
+
+    let mut local = Env::with_parent(program.name.clone(), Rc::new(RefCell::new(program.clone())));
+    local.is_unsafe |= is_unsafe;
+
+    let local = exec(exprs, &Rc::new(RefCell::new(local)))?;
+
+    if local.retyet {
+        if let Some(name) = program.name.clone() {
+            if let Some(value) = local.retval.borrow().get(&name) {
+                program.set_ret(&name, value.clone());
+            }
+        }
+
+        program.retyet = true;
+    }
+
+    Ok(none())
+}
+
 pub fn exec<'a>(
-    many: Vec<Span<Expr<'a>>>,
+    many: Vec<Span<Expr>>,
     env: &Rc<RefCell<Env<'a>>>,
 ) -> Result<Env<'a>, Span<String>> {
+    if env.borrow().backend == Backend::Vm {
+        return vm::exec(&many, env);
+    }
+
     for i in many {
+        let returns = matches!(i.0, Expr::Return { .. });
         interp(&mut env.borrow_mut(), i)?;
+
+        // a `return` in a nested block skips the rest of this one
+        if env.borrow().retyet && !returns {
+            break;
+        }
     }
 
     Ok(env.borrow().clone())
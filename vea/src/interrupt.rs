@@ -0,0 +1,56 @@
+//! Stopping a program from outside of it, e.g. from another thread or once it has run for too long.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    common::Tag,
+    span::{RawSpan, Span},
+};
+
+/// A handle that cancels a program. Clones of it cancel the same program, and can be sent to other
+/// threads. The program checks it at the end of every loop iteration and at every fn call.
+#[derive(Clone, Debug, Default)]
+pub struct Interrupt {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl Interrupt {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A handle that also cancels the program once `timeout` has passed from now.
+    #[must_use]
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            deadline: Instant::now().checked_add(timeout),
+            ..Self::default()
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.deadline.is_some_and(|x| Instant::now() >= x)
+    }
+
+    /// Fails if the program has been cancelled, blaming the statement at `span`.
+    pub fn check(&self, span: RawSpan) -> Result<(), Span<String>> {
+        if self.is_cancelled() {
+            return Err("execution cancelled".to_string().t(span));
+        }
+
+        Ok(())
+    }
+}
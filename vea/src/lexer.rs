@@ -4,28 +4,40 @@ use chumsky::prelude::*;
 use crate::choice;
 use crate::common::VeaErr;
 use crate::span::Span;
+use crate::symbol::Symbol;
 // use crate::void;
 // use crate::special_chars;
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
-pub enum Token<'a> {
-    Ident(&'a str), // abc
+pub enum Token {
+    Ident(Symbol),  // abc
     Number(i64),    // 123
     String(String), // 'abc'
+    // 'a {b} c {d:x} e' is InterpStart, the tokens of `b`, InterpMid, the tokens of `d`, FormatSpec,
+    // then InterpEnd
+    InterpStart(String), // 'a {
+    InterpMid(String),   // } c {
+    InterpEnd(String),   // } e'
+    FormatSpec(String),  // :x
     Imul(i64, String),
 
-    Let,    // let
-    If,     // if
-    Else,   // else
-    Print,  // print
-    True,   // true
-    False,  // false
-    While,  // while
-    For,    // for
-    Fn,     // fn
-    Return, // return
-    Struct, // struct
-    Set,    // set
+    Let,     // let
+    If,      // if
+    Else,    // else
+    Print,   // print
+    Println, // println
+    True,    // true
+    False,   // false
+    While,   // while
+    For,     // for
+    Fn,      // fn
+    Return,  // return
+    Struct,  // struct
+    Set,     // set
+    Lock,    // lock
+    Use,     // use
+    Unsafe,  // unsafe
+    This,    // self
 
     Quote, // '
 
@@ -60,6 +72,11 @@ pub enum Token<'a> {
     Lt,         // <
     Le,         // <=
     Tilde,      // ~
+    GtTilde,    // >~
+    GeTilde,    // >=~
+    LtTilde,    // <~
+    LeTilde,    // <=~
+    EqEqTilde,  // ==~
     Question,   // ?
 
     LeftBrace,    // {
@@ -77,18 +94,18 @@ pub enum Token<'a> {
     Error(VeaErr),
 }
 
-pub fn lexer<'s>(
-) -> impl Parser<'s, &'s str, Vec<Span<Token<'s>>>, chumsky::extra::Err<Rich<'s, char>>> {
+#[must_use]
+pub fn lexer<'s>() -> impl Parser<'s, &'s str, Vec<Span<Token>>, chumsky::extra::Err<Rich<'s, char>>>
+{
     let num: _ = text::int(10)
         .from_str()
         .map(|x| x.map_or(Token::Error(VeaErr::IntegerOverflow), Token::Number))
         .labelled("integer");
 
-    let ident: _ = one_of("abcdefghijklmnopqrstuvwxyz_")
-        .repeated()
-        .at_least(1)
+    let ident: _ = one_of("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ_")
+        .then(one_of("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ_0123456789").repeated())
         .and_is(just("_").not())
-        .map_slice(Token::Ident)
+        .map_slice(|x: &str| Token::Ident(Symbol::from(x)))
         .boxed()
         .labelled("ident");
 
@@ -96,14 +113,24 @@ pub fn lexer<'s>(
 
     let string: _ = quote_char
         .clone()
-        .ignore_then(none_of('\'').repeated())
+        .ignore_then(none_of('\'').repeated().map_slice(|x: &str| x))
         .then_ignore(quote_char)
-        .map_slice(|x: &str| Token::String(x.to_owned()))
+        .validate(|x, s, emitter| {
+            interpolate(x, s).unwrap_or_else(|e| {
+                emitter.emit(e);
+                vec![Span(Token::String(x.to_owned()), s)]
+            })
+        })
         .boxed()
         .labelled("string");
 
     let op: _ = choice! {
         just("::").to(Token::DoubleColon),
+        just(">=~").to(Token::GeTilde),
+        just("<=~").to(Token::LeTilde),
+        just("==~").to(Token::EqEqTilde),
+        just(">~").to(Token::GtTilde),
+        just("<~").to(Token::LtTilde),
         just("&=").to(Token::AndEq),
         just("!=").to(Token::Ne),
         just("==").to(Token::EqEq),
@@ -160,6 +187,7 @@ pub fn lexer<'s>(
         just("else").to(Token::Else),
         just("true").to(Token::True),
         just("false").to(Token::False),
+        just("println").to(Token::Println),
         just("print").to(Token::Print),
         just("while").to(Token::While),
         just("for").to(Token::For),
@@ -168,7 +196,13 @@ pub fn lexer<'s>(
         just("yield").to(Token::Return),
         just("struct").to(Token::Struct),
         just("set").to(Token::Set),
+        just("lock").to(Token::Lock),
+        just("use").to(Token::Use),
+        just("unsafe").to(Token::Unsafe),
+        just("self").to(Token::This),
     }
+    // `letter` is an identifier, not `let` followed by `ter`
+    .then_ignore(one_of("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ_0123456789").not())
     .boxed()
     .labelled("keyword");
 
@@ -180,19 +214,136 @@ pub fn lexer<'s>(
 
     let token: _ = num
         .or(kw)
-        .or(string)
         .or(ident)
         .or(op)
         .or(ctrl)
         // .or(quote_char)
+        .map_with_span(|x, s| vec![Span(x, s)])
+        .or(string)
         .boxed()
         .labelled("token");
 
     token
-        .map_with_span(Span)
         .padded_by(comment.repeated())
         .padded()
         .recover_with(skip_then_retry_until(any().ignored(), end()))
         .repeated()
-        .collect()
+        .collect::<Vec<_>>()
+        .map(|x| x.into_iter().flatten().collect())
+}
+
+/// Splits the inside of a string literal into its text and `{...}` holes, lexing every hole.
+/// `{{` and `}}` stand for literal braces.
+fn interpolate(src: &str, span: SimpleSpan) -> Result<Vec<Span<Token>>, Rich<'_, char>> {
+    // byte offsets into `src`, and the spans of the tokens around it
+    let at = |a: usize, b: usize| SimpleSpan::new(span.start + 1 + a, span.start + 1 + b);
+
+    let mut tokens = vec![];
+    let mut text = String::new();
+    let mut text_start = 0;
+    let mut chars = src.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' if chars.peek().is_some_and(|&(_, c)| c == '{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek().is_some_and(|&(_, c)| c == '}') => {
+                chars.next();
+                text.push('}');
+            }
+            '}' => {
+                return Err(Rich::custom(
+                    at(i, i + 1),
+                    "unmatched `}` in string, use `}}` for a literal brace",
+                ));
+            }
+            '{' => {
+                let text_span = if tokens.is_empty() {
+                    SimpleSpan::new(span.start, span.start + 1 + i + 1)
+                } else {
+                    at(text_start, i + 1)
+                };
+
+                tokens.push(Span(
+                    if tokens.is_empty() {
+                        Token::InterpStart(std::mem::take(&mut text))
+                    } else {
+                        Token::InterpMid(std::mem::take(&mut text))
+                    },
+                    text_span,
+                ));
+
+                // the hole ends at the matching `}`, and its format spec at the first lone `:`
+                let (mut depth, mut colon, mut end) = (0, None, None);
+
+                while let Some((j, c)) = chars.next() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' if depth == 0 => {
+                            end = Some(j);
+                            break;
+                        }
+                        '}' => depth -= 1,
+                        ':' if chars.peek().is_some_and(|&(_, c)| c == ':') => {
+                            chars.next();
+                        }
+                        ':' if depth == 0 && colon.is_none() => colon = Some(j),
+                        _ => {}
+                    }
+                }
+
+                let Some(end) = end else {
+                    return Err(Rich::custom(
+                        at(i, src.len()),
+                        "unclosed `{` in string, use `{{` for a literal brace",
+                    ));
+                };
+
+                let expr_end = colon.unwrap_or(end);
+
+                if src[i + 1..expr_end].trim().is_empty() {
+                    return Err(Rich::custom(
+                        at(i, end + 1),
+                        "empty `{}` in string, use `{{}}` for literal braces",
+                    ));
+                }
+
+                let (hole, errors) = lexer().parse(&src[i + 1..expr_end]).into_output_errors();
+
+                if let Some(e) = errors.first() {
+                    let s = e.span();
+                    return Err(Rich::custom(at(i + 1 + s.start, i + 1 + s.end), e.reason()));
+                }
+
+                tokens.extend(
+                    hole.unwrap_or_default()
+                        .into_iter()
+                        .map(|Span(x, s)| Span(x, at(i + 1 + s.start, i + 1 + s.end))),
+                );
+
+                if let Some(colon) = colon {
+                    tokens.push(Span(
+                        Token::FormatSpec(src[colon + 1..end].to_owned()),
+                        at(colon, end),
+                    ));
+                }
+
+                text_start = end;
+            }
+            c => text.push(c),
+        }
+    }
+
+    if tokens.is_empty() {
+        return Ok(vec![Span(Token::String(text), span)]);
+    }
+
+    tokens.push(Span(
+        Token::InterpEnd(text),
+        SimpleSpan::new(span.start + 1 + text_start, span.end),
+    ));
+
+    Ok(tokens)
 }
@@ -15,7 +15,7 @@
     clippy::let_with_type_underscore
 )]
 use std::cell::RefCell;
-use std::io::Write;
+use std::fmt::Write;
 use std::rc::Rc;
 
 use ariadne::sources;
@@ -32,16 +32,32 @@ use lexer::lexer;
 
 use span::Span;
 
-use crate::interpreter::exec;
-use crate::parser::parser;
+use crate::common::grow;
+use crate::common::Tag;
+use crate::interpreter::{call, exec};
+use crate::limits::MAX_NESTING;
+use crate::parser::parse_at;
 
 pub mod ast;
 pub mod common;
+pub mod compiler;
+pub mod format;
+pub mod gc;
+pub mod input;
 pub mod interpreter;
+pub mod interrupt;
 pub mod lexer;
+pub mod limits;
 pub mod literal;
+pub mod module;
+pub mod output;
 pub mod parser;
+pub mod prelude;
+pub mod resolver;
 pub mod span;
+pub mod stdlib;
+pub mod symbol;
+pub mod vm;
 // #[doc(hidden)]
 // mod special_chars;
 pub mod display;
@@ -80,29 +96,54 @@ pub fn lex(src: &str) -> (Option<Vec<Span<lexer::Token>>>, String) {
 }
 
 #[must_use]
-pub fn parse<'t>(
-    src: &str,
-    b: &[Span<lexer::Token<'t>>],
-) -> (Option<Vec<Span<ast::Expr<'t>>>>, String) {
-    let a = b.iter().map(|x| x.0.clone()).collect::<Vec<_>>();
-    let p = parser().parse(a.as_slice()).into_output_errors();
+pub fn parse(src: &str, b: &[Span<lexer::Token>]) -> (Option<Vec<Span<ast::Expr>>>, String) {
+    parse_after(src, b, 0)
+}
 
+/// Like [`parse`], but with the spans of the AST after `offset` other tokens, as with
+/// [`parser::parse_at`].
+fn parse_after(
+    src: &str,
+    b: &[Span<lexer::Token>],
+    offset: usize,
+) -> (Option<Vec<Span<ast::Expr>>>, String) {
     let mut stdo = String::new();
 
+    // refused up front, as the parser would take a lot of memory to get that deep
+    if let Some(x) = too_deep(b) {
+        let message = format!("expression is nested more than {MAX_NESTING} levels deep");
+
+        Report::build(ReportKind::Error, "test.vea", x.1.start)
+            .with_message(&message)
+            .with_label(
+                Label::new(("test.vea", x.1.into_range()))
+                    .with_message(&message)
+                    .with_color(Color::Red),
+            )
+            .finish()
+            .write_for_stdout(sources([("test.vea", src)]), unsafe { stdo.as_mut_vec() })
+            .unwrap();
+
+        return (None, stdo);
+    }
+
+    let a = b.iter().map(|x| x.0.clone()).collect::<Vec<_>>();
+    // the parser recurses once for each level of nesting, and grows its stack as it goes
+    let p = grow(|| parse_at(&a, offset));
+
     p.1.clone()
         .into_iter()
         .map(|x: _| x.map_token(|c: _| ariadne::Color::Green.paint(c.to_string())))
         .for_each(|x: _| {
-            Report::build(ReportKind::Error, "test.vea", b[x.span().start].1.start)
+            let (start, end) = (x.span().start - offset, x.span().end - offset);
+
+            Report::build(ReportKind::Error, "test.vea", b[start].1.start)
                 // .with_config(Config::default().with_char_set(CharSet::Ascii))
                 .with_message("")
                 .with_label(
-                    Label::new((
-                        "test.vea",
-                        b[x.span().start].1.start..b[x.span().end - 1].1.end,
-                    ))
-                    .with_message(x.reason().to_string())
-                    .with_color(Color::Red),
+                    Label::new(("test.vea", b[start].1.start..b[end - 1].1.end))
+                        .with_message(x.reason().to_string())
+                        .with_color(Color::Red),
                 )
                 .finish()
                 .write_for_stdout(sources([("test.vea", src)]), unsafe { stdo.as_mut_vec() })
@@ -112,32 +153,333 @@ pub fn parse<'t>(
     (p.0, stdo)
 }
 
+/// The first token that nests an expression more than [`MAX_NESTING`] levels deep, or may.
+///
+/// A bracket opens a level, and so does any operator or keyword, as with `-` in `- - 1` or `+` in
+/// `1 + 1 + 1`. Those of a bracket count until its next `,` or `;`, or a `}` that ends a statement.
+fn too_deep(tokens: &[Span<lexer::Token>]) -> Option<&Span<lexer::Token>> {
+    use lexer::Token;
+
+    // the operators since the last expression started, for every open bracket
+    let mut levels = vec![0usize];
+    let mut depth = 0usize;
+
+    tokens.iter().enumerate().find_map(|(i, x)| {
+        let ops = levels.last_mut().unwrap();
+
+        match x.0 {
+            Token::LeftParen | Token::LeftBracket | Token::LeftBrace | Token::InterpStart(_) => {
+                levels.push(0);
+                depth += 1;
+            }
+            Token::RightParen | Token::RightBracket | Token::RightBrace | Token::InterpEnd(_) => {
+                if levels.len() > 1 {
+                    depth -= 1 + levels.pop().unwrap();
+                }
+
+                let ops = levels.last_mut().unwrap();
+                let next = tokens.get(i + 1).map(|x| &x.0);
+
+                if x.0 == Token::RightBrace && next.is_none_or(starts_statement) {
+                    depth -= *ops;
+                    *ops = 0;
+                } else {
+                    // it is itself one expression inside of the next
+                    *ops += 1;
+                    depth += 1;
+                }
+            }
+            Token::Comma | Token::Semi | Token::InterpMid(_) => {
+                depth -= *ops;
+                *ops = 0;
+            }
+            Token::Ident(_)
+            | Token::Number(_)
+            | Token::String(_)
+            | Token::Imul(..)
+            | Token::FormatSpec(_)
+            | Token::True
+            | Token::False
+            | Token::Underscore
+            | Token::This
+            | Token::Error(_) => {}
+            _ => {
+                *ops += 1;
+                depth += 1;
+            }
+        }
+
+        (depth > MAX_NESTING).then_some(x)
+    })
+}
+
+/// Whether a statement can start with `token`, so that it can't continue what comes before it.
+const fn starts_statement(token: &lexer::Token) -> bool {
+    use lexer::Token;
+
+    matches!(
+        token,
+        Token::Ident(_)
+            | Token::This
+            | Token::Let
+            | Token::If
+            | Token::While
+            | Token::For
+            | Token::Fn
+            | Token::Return
+            | Token::Print
+            | Token::Println
+            | Token::Lock
+            | Token::Use
+            | Token::Unsafe
+            | Token::RightBrace
+    )
+}
+
+#[must_use]
+pub fn interp(src: &str, t: &[Span<lexer::Token>], p: Vec<Span<ast::Expr>>) -> String {
+    interp_with_input(src, t, p, input::Text::default())
+}
+
+/// Runs a program that reads from `input`, like [`input::Stdin`] or a fixed [`input::Text`].
+#[must_use]
+pub fn interp_with_input(
+    src: &str,
+    t: &[Span<lexer::Token>],
+    p: Vec<Span<ast::Expr>>,
+    input: impl input::Input + 'static,
+) -> String {
+    let env = new_env();
+    env.borrow_mut().input = Rc::new(RefCell::new(input));
+
+    let out = interp_in(src, t, p, &env);
+    gc::free(env);
+
+    out
+}
+
+/// Runs a program that is stopped with an error once it goes over any of `limits`.
+#[must_use]
+pub fn interp_with_limits(
+    src: &str,
+    t: &[Span<lexer::Token>],
+    p: Vec<Span<ast::Expr>>,
+    limits: limits::Limits,
+) -> String {
+    let env = new_env();
+    env.borrow_mut().meter = Rc::new(limits::Meter::new(limits));
+
+    let out = interp_in(src, t, p, &env);
+    gc::free(env);
+
+    out
+}
+
+/// A fresh environment with the prelude, to be set up further before [`interp_in`], e.g. with
+/// [`vm::Backend::Vm`] to run on the bytecode VM, or an [`output::Stream`] to print as it runs.
+///
+/// Its stdout and stderr go to the same buffer, in the order they are written.
+#[must_use]
+pub fn new_env<'a>() -> Rc<RefCell<Env<'a>>> {
+    let mut env = Env::new(None);
+    env.stderr = env.stdout.clone();
+    prelude::prelude(&env);
+    Rc::new(RefCell::new(env))
+}
+
+/// Runs a program in `env`, which can be set up beforehand, e.g. with a module search path. Gives
+/// back what its stdout kept, followed by the error it stopped at, if any.
+#[must_use]
+pub fn interp_in(
+    src: &str,
+    t: &[Span<lexer::Token>],
+    p: Vec<Span<ast::Expr>>,
+    env: &Rc<RefCell<Env<'_>>>,
+) -> String {
+    env.borrow().modules.borrow_mut().base = t.len();
+    run_in(src, t, p, env)
+}
+
+/// Runs `src` in an `env` that has run other programs before, like the next input of a REPL.
+///
+/// Whatever those declared is still there. Its tokens are numbered after all of theirs, which keeps
+/// them apart for the resolver, the VM and error reports.
 #[must_use]
-pub fn interp(src: &str, t: &[Span<lexer::Token<'_>>], p: Vec<Span<ast::Expr>>) -> String {
-    let env = Rc::new(RefCell::new(Env::new(None)));
+pub fn interp_next(src: &str, env: &Rc<RefCell<Env<'_>>>) -> String {
+    let (tokens, mut stdo) = lex(src);
+
+    let Some(tokens) = tokens else {
+        return stdo;
+    };
+
+    let offset = env.borrow().modules.borrow().next_offset();
+    let (exprs, errors) = parse_after(src, &tokens, offset);
+    stdo += &errors;
+
+    let Some(exprs) = exprs else {
+        return stdo;
+    };
+
+    env.borrow().modules.borrow_mut().files.push(module::File {
+        name: "test.vea".to_owned(),
+        src: src.to_owned(),
+        tokens: tokens.clone(),
+        offset,
+    });
+
+    stdo + &run_in(src, &tokens, exprs, env)
+}
+
+fn run_in(
+    src: &str,
+    t: &[Span<lexer::Token>],
+    p: Vec<Span<ast::Expr>>,
+    env: &Rc<RefCell<Env<'_>>>,
+) -> String {
+    env.borrow().trace.borrow_mut().clear();
+
+    let resolved = resolver::resolve(&p, &env.borrow());
+    let result = resolved.and_then(|()| exec(p, env));
+
+    let env = env.borrow();
+    let _ = env.stdout.borrow_mut().flush();
+    let _ = env.stderr.borrow_mut().flush();
+    let mut stdo = env.stdout.borrow_mut().take();
+
+    if let Err(e) = result {
+        stdo += &report(src, t, &env, &e);
+    }
+
+    stdo
+}
+
+/// Runs every `test fn` of a program, and reports which ones passed.
+#[must_use]
+pub fn test(src: &str, t: &[Span<lexer::Token>], p: &[Span<ast::Expr>]) -> String {
+    test_in(src, t, p, new_env)
+}
+
+/// Runs every `test fn` of a program, each in a fresh environment from `new_env`. The rest of the
+/// program is run before every test, so that the test can use what it declares.
+#[must_use]
+pub fn test_in<'a>(
+    src: &str,
+    t: &[Span<lexer::Token>],
+    p: &[Span<ast::Expr>],
+    new_env: impl Fn() -> Rc<RefCell<Env<'a>>>,
+) -> String {
+    let tests = p
+        .iter()
+        .filter_map(|Span(x, _)| match x {
+            ast::Expr::FnDecl {
+                test_token: Some(_),
+                name,
+                ..
+            } => Some(name.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut stdo = format!("running {} tests\n", tests.len());
+    let (mut passed, mut failures) = (0, vec![]);
 
-    let e = exec(p, &env);
+    for Span(name, span) in tests {
+        let env = new_env();
+        env.borrow().modules.borrow_mut().base = t.len();
 
+        let resolved = resolver::resolve(p, &env.borrow());
+        let result = resolved
+            .and_then(|()| exec(p.to_vec(), &env))
+            .and_then(|_| {
+                // called like any other fn, so that `return` and the limits work the same
+                let f = env.borrow().get(&name).ok_or_else(|| {
+                    format!("internal error: test `{name}` was never declared").t(span)
+                })?;
+                let f = f.borrow().clone();
+                let mut program = Env::sharing(None, &env.borrow());
+
+                call(&mut program, &f, vec![], span)
+            });
+
+        match result {
+            Ok(_) => {
+                passed += 1;
+                writeln!(stdo, "test {name} ... ok").unwrap();
+            }
+            Err(e) => {
+                writeln!(stdo, "test {name} ... FAILED").unwrap();
+                failures.push(report(src, t, &env.borrow(), &e));
+            }
+        }
+
+        gc::free(env);
+    }
+
+    if !failures.is_empty() {
+        stdo += "\nfailures:\n";
+        stdo += &failures.concat();
+    }
+
+    writeln!(
+        stdo,
+        "\ntest result: {}. {passed} passed; {} failed",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        failures.len()
+    )
+    .unwrap();
+
+    stdo
+}
+
+/// Formats an error raised while running, along with the trace it left in `env`. Either can come
+/// from any loaded module, not just the main file.
+fn report(src: &str, t: &[Span<lexer::Token>], env: &Env<'_>, err: &Span<String>) -> String {
+    let modules = env.modules.borrow();
     let mut stdo = String::new();
 
-    if let Err(Span(x, y)) = &e {
-        // e.into_iter().for_each(|(x, y)| {
-        Report::build(ReportKind::Error, "test.vea", t[y.start].1.start)
-            // .with_config(Config::default().with_char_set(CharSet::Ascii))
-            .with_message(x.clone())
-            .with_label(
-                Label::new(("test.vea", t[y.start].1.start..t[y.end - 1].1.end))
-                    .with_message(x)
-                    .with_color(Color::Red),
-            )
-            .finish()
-            .write(sources([("test.vea", src)]), unsafe { stdo.as_mut_vec() })
-            .unwrap();
-        // });
-    } else if let Ok(p) = &e {
-        write!(unsafe { stdo.as_mut_vec() }, "{}", p.stdout).unwrap();
+    // the file and character range of a span of tokens
+    let locate = |y: span::RawSpan| {
+        let (name, src, t, offset) = modules.file(y.start).map_or_else(
+            || ("test.vea".to_owned(), src, t, 0),
+            |f| (f.name.clone(), &f.src, &f.tokens[..], f.offset),
+        );
+        let (start, end) = (y.start - offset, y.end.max(y.start + 1) - offset);
+
+        (name, src, t[start].1.start..t[end - 1].1.end)
+    };
+
+    let (name, src, range) = locate(err.1);
+    let mut files = vec![(name.clone(), src.to_owned())];
+
+    let mut report = Report::build(ReportKind::Error, name.clone(), range.start)
+        // .with_config(Config::default().with_char_set(CharSet::Ascii))
+        .with_message(err.0.clone())
+        .with_label(
+            Label::new((name, range))
+                .with_message(&err.0)
+                .with_color(Color::Red),
+        );
+
+    for (i, Span(note, y)) in env.trace.borrow().iter().enumerate() {
+        let (name, src, range) = locate(*y);
+
+        if files.iter().all(|(n, _)| *n != name) {
+            files.push((name.clone(), src.to_owned()));
+        }
+
+        report = report.with_label(
+            Label::new((name, range))
+                .with_message(note)
+                .with_color(Color::Yellow)
+                .with_order(i32::try_from(i + 1).unwrap_or(i32::MAX)),
+        );
     }
 
+    report
+        .finish()
+        .write(sources(files), unsafe { stdo.as_mut_vec() })
+        .unwrap();
+
     stdo
 }
 
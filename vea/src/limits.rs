@@ -0,0 +1,160 @@
+//! How much a program may do before it is stopped, for running code that cannot be trusted.
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use crate::{
+    common::Tag,
+    gc::Heap,
+    literal::Literal,
+    span::{RawSpan, Span},
+};
+
+/// How many fn calls may be running at once, whatever the limits. A call in tail position, like
+/// `return f(x);`, does not count, as it replaces the call that makes it.
+pub const MAX_DEPTH: usize = 1000;
+
+/// How deep expressions and blocks may nest inside of each other. Each level takes some memory to
+/// run, so a program that nests deeper is refused before it runs.
+pub const MAX_NESTING: usize = 5000;
+
+/// How big a value checked with [`Meter::size`] may be, whatever the limits, so that asking for more
+/// memory than there is fails with an error instead of aborting the process.
+pub const MAX_SIZE: usize = 1 << 30;
+
+/// The limits of a program. `None` is no limit, which is the default for each.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Limits {
+    /// How many steps it may take. A step is one iteration of a loop, or one call of a fn that is not
+    /// native, so that both backends take as many for the same program.
+    pub steps: Option<u64>,
+    /// How many fn calls may be running at once.
+    pub depth: Option<usize>,
+    /// How long a string may be, in bytes, and how many items an array, set or object may have.
+    pub size: Option<usize>,
+    /// How many arrays, sets, objects, envs and fns may be alive at once. Values that are freed
+    /// again, like a temporary array in a loop, do not count towards it.
+    pub allocations: Option<u64>,
+}
+
+/// Keeps count of what a program has used so far, and stops it once it goes over its [`Limits`].
+#[derive(Debug, Default)]
+pub struct Meter {
+    pub limits: Limits,
+    steps: Cell<u64>,
+    depth: Cell<usize>,
+    /// How many values the heap tracks before they are counted again.
+    recount: Cell<usize>,
+}
+
+impl Meter {
+    #[must_use]
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub const fn steps(&self) -> u64 {
+        self.steps.get()
+    }
+
+    #[must_use]
+    pub const fn depth(&self) -> usize {
+        self.depth.get()
+    }
+
+    /// Takes one step, the one at `span`.
+    pub fn step(&self, span: RawSpan) -> Result<(), Span<String>> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+
+        match self.limits.steps {
+            Some(max) if steps > max => Err(format!("ran out of steps after {max}").t(span)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Enters the fn called at `span`, until [`Self::leave`].
+    pub fn enter(&self, span: RawSpan) -> Result<(), Span<String>> {
+        let depth = self.depth.get() + 1;
+
+        if let Some(max) = self.limits.depth {
+            if depth > max {
+                return Err(format!("exceeded the maximum call depth of {max}").t(span));
+            }
+        }
+
+        if depth > MAX_DEPTH {
+            return Err(format!("exceeded the maximum recursion depth of {MAX_DEPTH}").t(span));
+        }
+
+        self.depth.set(depth);
+        Ok(())
+    }
+
+    pub fn leave(&self) {
+        self.depth.set(self.depth.get().saturating_sub(1));
+    }
+
+    /// Checks that no more values are alive in `heap` than the limit allows, once it tracks more than
+    /// that. Only then are the freed ones told apart, and the next time is as far away as there
+    /// were values still alive, so that counting them takes as long as making them did.
+    pub fn live(&self, heap: &Heap<'_>, span: RawSpan) -> Result<(), Span<String>> {
+        let Some(max) = self.limits.allocations else {
+            return Ok(());
+        };
+        let max = usize::try_from(max).unwrap_or(usize::MAX);
+
+        if heap.tracked() <= self.recount.get().max(max) {
+            return Ok(());
+        }
+
+        let live = heap.prune();
+        if live > max {
+            return Err(format!("exceeded the limit of {max} allocations").t(span));
+        }
+
+        self.recount.set(max.saturating_add(live));
+        Ok(())
+    }
+
+    /// Checks the size of `value`, if it has one.
+    pub fn value(
+        &self,
+        value: &Rc<RefCell<Literal<'_>>>,
+        span: RawSpan,
+    ) -> Result<(), Span<String>> {
+        if self.limits.size.is_none() {
+            return Ok(());
+        }
+
+        let size = match &*value.borrow() {
+            Literal::String(x) => x.len(),
+            Literal::Array(x, _) | Literal::Set(x) => x.len(),
+            Literal::Object(x, _) => x.len(),
+            Literal::Env(x) => x.borrow().len(),
+            _ => return Ok(()),
+        };
+
+        self.size(&value.borrow().type_of(), size, span)
+    }
+
+    /// Checks the size of a value of type `ty`, e.g. before it is made.
+    pub fn size(&self, ty: &str, size: usize, span: RawSpan) -> Result<(), Span<String>> {
+        let max = self.limits.size.map_or(MAX_SIZE, |max| max.min(MAX_SIZE));
+
+        if size > max {
+            return Err(format!(
+                "a value of type `{ty}` with a size of {size} exceeds the maximum size of {max}"
+            )
+            .t(span));
+        }
+
+        Ok(())
+    }
+}
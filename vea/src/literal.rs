@@ -1,72 +1,425 @@
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
     fmt::Display,
     ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub},
     rc::Rc,
 };
 
-use crate::{ast::Expr, span::Span};
+use crate::{
+    ast::Expr,
+    env::{Env, Scope},
+    gc::Heap,
+    span::{RawSpan, Span},
+    symbol::Symbol,
+};
 
 #[derive(Clone, Debug)]
 pub enum Literal<'a> {
     Bool(bool),
     Integer(i64),
     String(String),
-    // name, args, body
-    Fn(Span<&'a str>, Vec<Span<&'a str>>, Box<Span<Expr<'a>>>),
-    Object(HashMap<&'a str, Rc<RefCell<Self>>>),
+    // name, args, body, modifiers
+    Fn(Span<Symbol>, Vec<Span<Symbol>>, Box<Span<Expr>>, FnKind<'a>),
+    // name, arity, implementation
+    NativeFn(&'a str, Arity, Native<'a>),
+    // fields, locked fields
+    Object(HashMap<Symbol, Rc<RefCell<Self>>>, HashSet<Symbol>),
     Set(Vec<Rc<RefCell<Self>>>),
+    // items, locked
+    Array(Vec<Rc<RefCell<Self>>>, bool),
+    Ordering(TotalOrdering),
+    // the variables of a fn, as given by `self`
+    Env(Scope<'a>),
     None,
 }
 
-impl<'a> Display for Literal<'a> {
+/// How a function was declared, apart from its name, arguments and body.
+#[derive(Clone, Debug, Default)]
+pub struct FnKind<'a> {
+    /// For methods like `some(T) { ... }`, the scope that `self` refers to.
+    pub this: Option<Scope<'a>>,
+    /// Declared with `unsafe`, so it can only be called from an `unsafe` block.
+    pub is_unsafe: bool,
+    /// Declared inside an `unsafe` block, so its body can do unsafe things.
+    pub unsafe_body: bool,
+    /// Declared with `test`, so it may end without returning anything.
+    pub is_test: bool,
+    /// The scope the fn was declared in, which is what its body sees besides its arguments.
+    pub scope: Option<Rc<RefCell<Env<'a>>>>,
+}
+
+/// How many arguments a native function takes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Arity {
+    pub min: usize,
+    /// `None` if there is no limit.
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    #[must_use]
+    pub const fn exactly(n: usize) -> Self {
+        Self {
+            min: n,
+            max: Some(n),
+        }
+    }
+
+    #[must_use]
+    pub const fn between(min: usize, max: usize) -> Self {
+        Self {
+            min,
+            max: Some(max),
+        }
+    }
+
+    #[must_use]
+    pub const fn at_least(min: usize) -> Self {
+        Self { min, max: None }
+    }
+
+    #[must_use]
+    pub fn accepts(self, n: usize) -> bool {
+        n >= self.min && self.max.is_none_or(|max| n <= max)
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{max}"),
+            Some(max) => write!(f, "{} to {max}", self.min),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
+}
+
+/// The arguments of a native function, each with the span of the expression it came from.
+pub type Args<'a> = [Arg<'a>];
+
+/// One argument of a native function, with the span of the expression it came from.
+pub type Arg<'a> = Span<Rc<RefCell<Literal<'a>>>>;
+
+/// The Rust side of a [`Literal::NativeFn`]. It gets the evaluated arguments and the span of the
+/// whole call.
+#[derive(Clone)]
+pub struct Native<'a>(
+    #[allow(clippy::type_complexity)]
+    pub  Rc<
+        dyn Fn(&mut Env<'a>, &Args<'a>, RawSpan) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>>
+            + 'a,
+    >,
+);
+
+impl std::fmt::Debug for Native<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Native")
+    }
+}
+
+/// The result of `cmp`. Plain comparisons only ever produce the first six variants, the `Ordering`
+/// of the spec.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum TotalOrdering {
+    GreaterThan,
+    GreaterThanOrEqual,
+    LessThan,
+    LessThanOrEqual,
+    Equal,
+    NotEqual,
+    AlmostGreaterThan,
+    AlmostGreaterThanOrEqual,
+    AlmostLessThan,
+    AlmostLessThanOrEqual,
+    AlmostEqual,
+}
+
+impl From<Ordering> for TotalOrdering {
+    fn from(value: Ordering) -> Self {
+        match value {
+            Ordering::Greater => Self::GreaterThan,
+            Ordering::Less => Self::LessThan,
+            Ordering::Equal => Self::Equal,
+        }
+    }
+}
+
+impl Display for TotalOrdering {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
             "{}",
             match self {
-                Self::Bool(z) => z.to_string(),
-                Self::Integer(z) => z.to_string(),
-                Self::String(z) => z.to_string(),
-                Self::Fn(z, a, ..) => format!("fn {}({})", z.0, a.len()),
-                Self::Object(z) => format!(
-                    "object {{ {} }}",
-                    z.keys().copied().collect::<Vec<_>>().join(", ")
-                ),
-                Self::Set(p) => format!(
-                    "set {{ {} }}",
-                    p.iter()
-                        .map(|x| x.borrow().to_string())
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                ),
-                Self::None => "_".to_string(),
+                Self::GreaterThan => ">",
+                Self::GreaterThanOrEqual => ">=",
+                Self::LessThan => "<",
+                Self::LessThanOrEqual => "<=",
+                Self::Equal => "==",
+                Self::NotEqual => "!=",
+                Self::AlmostGreaterThan => ">~",
+                Self::AlmostGreaterThanOrEqual => ">=~",
+                Self::AlmostLessThan => "<~",
+                Self::AlmostLessThanOrEqual => "<=~",
+                Self::AlmostEqual => "==~",
             }
         )
     }
 }
 
+thread_local! {
+    /// The values being displayed right now, so that a value that contains itself shows up as
+    /// `...` instead of overflowing the stack.
+    static DISPLAYING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+thread_local! {
+    /// The pairs of values being compared right now, so that comparing a value that contains itself
+    /// ends instead of overflowing the stack.
+    static COMPARING: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Compares `l` with `r` by `f`, unless they are already being compared further up, as they are in
+/// a value that contains itself. Then they are taken to be `same`.
+fn compare_once<'a, T>(
+    l: &Rc<RefCell<Literal<'a>>>,
+    r: &Rc<RefCell<Literal<'a>>>,
+    same: T,
+    f: impl FnOnce(&Literal<'a>, &Literal<'a>) -> Result<T, String>,
+) -> Result<T, String> {
+    let key = (Rc::as_ptr(l) as usize, Rc::as_ptr(r) as usize);
+
+    if COMPARING.with_borrow(|x| x.contains(&key)) {
+        return Ok(same);
+    }
+
+    COMPARING.with_borrow_mut(|x| x.push(key));
+    let out = f(&l.borrow(), &r.borrow());
+    COMPARING.with_borrow_mut(Vec::pop);
+
+    out
+}
+
+/// Copies the cell `value` for [`Literal::deep_copy`], or gives back its copy if it was already made,
+/// by its address in `copies`.
+fn copy_cell<'a>(
+    value: &Rc<RefCell<Literal<'a>>>,
+    heap: &Heap<'a>,
+    copies: &mut HashMap<usize, Rc<RefCell<Literal<'a>>>>,
+) -> Rc<RefCell<Literal<'a>>> {
+    let key = Rc::as_ptr(value) as usize;
+
+    if let Some(copy) = copies.get(&key) {
+        return copy.clone();
+    }
+
+    // made before what is inside of it, which may refer back to it
+    let copy = Rc::new(RefCell::new(Literal::None));
+    copies.insert(key, copy.clone());
+
+    let inner = value.borrow().copy_with(heap, copies);
+    *copy.borrow_mut() = inner;
+    heap.adopt(&copy);
+
+    copy
+}
+
+/// Copies the variables of `scope` for [`Literal::deep_copy`], or gives back its copy if it was
+/// already made, keyed by its address in `copies` as the `env` value of the copy.
+fn copy_scope<'a>(
+    scope: &Scope<'a>,
+    heap: &Heap<'a>,
+    copies: &mut HashMap<usize, Rc<RefCell<Literal<'a>>>>,
+) -> Scope<'a> {
+    let key = Rc::as_ptr(scope) as usize;
+
+    if let Some(Literal::Env(copy)) = copies.get(&key).map(|x| x.borrow().clone()) {
+        return copy;
+    }
+
+    let copy = Scope::default();
+    copies.insert(key, Rc::new(RefCell::new(Literal::Env(copy.clone()))));
+
+    let frame = scope.borrow().map(|v| copy_cell(v, heap, copies));
+    *copy.borrow_mut() = frame;
+
+    copy
+}
+
+/// `a: x, b: y`, sorted by name.
+fn fields<'a, 'k>(fields: impl Iterator<Item = (&'k str, &'k Rc<RefCell<Literal<'a>>>)>) -> String
+where
+    'a: 'k,
+{
+    let mut fields = fields
+        .map(|(k, v)| format!("{k}: {}", v.borrow()))
+        .collect::<Vec<_>>();
+
+    fields.sort();
+    fields.join(", ")
+}
+
+impl Display for Literal<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = match self {
+            Self::Env(z) => Rc::as_ptr(z) as usize,
+            Self::Object(..) | Self::Set(..) | Self::Array(..) => std::ptr::from_ref(self) as usize,
+            _ => 0,
+        };
+
+        if id != 0 {
+            if DISPLAYING.with_borrow(|x| x.contains(&id)) {
+                return write!(f, "...");
+            }
+
+            DISPLAYING.with_borrow_mut(|x| x.push(id));
+        }
+
+        let text = match self {
+            Self::Bool(z) => z.to_string(),
+            Self::Integer(z) => z.to_string(),
+            Self::String(z) => z.clone(),
+            Self::Fn(z, a, ..) => format!("fn {}({})", z.0, a.len()),
+            Self::NativeFn(z, a, ..) => format!("fn {z}({a})"),
+            Self::Object(z, ..) => {
+                format!("object {{ {} }}", fields(z.iter().map(|(k, v)| (&**k, v))))
+            }
+            Self::Set(p) => format!(
+                "set {{ {} }}",
+                p.iter()
+                    .map(|x| x.borrow().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::Array(p, _) => format!(
+                "[{}]",
+                p.iter()
+                    .map(|x| x.borrow().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::Ordering(o) => o.to_string(),
+            Self::Env(z) => format!(
+                "env {{ {} }}",
+                fields(z.borrow().iter().map(|(k, v)| (k.as_str(), v)))
+            ),
+            Self::None => "_".to_string(),
+        };
+
+        if id != 0 {
+            DISPLAYING.with_borrow_mut(Vec::pop);
+        }
+
+        write!(f, "{text}")
+    }
+}
+
 impl<'a> Literal<'a> {
+    /// Every name [`Self::type_of`] can return, except `_`.
+    pub const TYPES: &'static [&'static str] = &[
+        "bool", "int", "str", "fn", "object", "set", "array", "ordering", "env",
+    ];
+
     #[must_use]
     pub fn type_of(&self) -> String {
         match self {
             Self::Bool(..) => "bool",
             Self::Integer(..) => "int",
             Self::String(..) => "str",
-            Self::Fn(..) => "fn",
+            Self::Fn(..) | Self::NativeFn(..) => "fn",
             Self::Object(..) => "object",
             Self::None => "_",
             Self::Set(..) => "set",
+            Self::Array(..) => "array",
+            Self::Ordering(..) => "ordering",
+            Self::Env(..) => "env",
         }
         .to_owned()
     }
 
+    /// Copies a value without sharing any of its inner cells, so mutating the copy never reaches the
+    /// original. A value that contains itself is copied into one that contains its copy, and every
+    /// cell of the copy is tracked by `heap`. Locks on object fields are not carried over.
+    #[must_use]
+    pub fn deep_copy(value: &Rc<RefCell<Self>>, heap: &Heap<'a>) -> Rc<RefCell<Self>> {
+        copy_cell(value, heap, &mut HashMap::new())
+    }
+
+    fn copy_with(&self, heap: &Heap<'a>, copies: &mut HashMap<usize, Rc<RefCell<Self>>>) -> Self {
+        match self {
+            Self::Object(fields, ..) => Self::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), copy_cell(v, heap, copies)))
+                    .collect(),
+                HashSet::new(),
+            ),
+            Self::Set(values) => {
+                Self::Set(values.iter().map(|v| copy_cell(v, heap, copies)).collect())
+            }
+            Self::Array(values, _) => Self::Array(
+                values.iter().map(|v| copy_cell(v, heap, copies)).collect(),
+                false,
+            ),
+            Self::Env(scope) => Self::Env(copy_scope(scope, heap, copies)),
+            c => c.clone(),
+        }
+    }
+
+    /// Locks `value` and everything inside of it, so that it can't be changed through any variable
+    /// that refers to it. The variables of an `env` stay mutable unless locked one by one.
+    pub fn lock(value: &Rc<RefCell<Self>>) {
+        let mut todo = vec![value.clone()];
+        let mut seen = HashSet::new();
+
+        while let Some(value) = todo.pop() {
+            if !seen.insert(Rc::as_ptr(&value) as usize) {
+                continue;
+            }
+
+            match &mut *value.borrow_mut() {
+                Self::Array(values, locked) => {
+                    *locked = true;
+                    todo.extend(values.iter().cloned());
+                }
+                Self::Object(fields, locked) => {
+                    locked.extend(fields.keys().cloned());
+                    todo.extend(fields.values().cloned());
+                }
+                Self::Set(values) => todo.extend(values.iter().cloned()),
+                _ => {}
+            }
+        }
+    }
+
     pub fn req(self, rhs: Self) -> Result<Self, String> {
         match (self, rhs) {
             (Self::Bool(lhs), Self::Bool(rhs)) => Ok(Self::Bool(lhs == rhs)),
             (Self::String(lhs), Self::String(rhs)) => Ok(Self::Bool(lhs == rhs)),
             (Self::Integer(lhs), Self::Integer(rhs)) => Ok(Self::Bool(lhs == rhs)),
+            (Self::Ordering(lhs), Self::Ordering(rhs)) => Ok(Self::Bool(lhs == rhs)),
+            (Self::None, Self::None) => Ok(Self::Bool(true)),
+            (Self::None, _) | (_, Self::None) => Ok(Self::Bool(false)),
+            (Self::Array(lhs, _), Self::Array(rhs, _)) => {
+                if lhs.len() != rhs.len() {
+                    return Ok(Self::Bool(false));
+                }
+
+                for (l, r) in lhs.iter().zip(&rhs) {
+                    let eq = compare_once(l, r, Self::Bool(true), |l, r| l.clone().req(r.clone()))?;
+
+                    if matches!(eq, Self::Bool(false)) {
+                        return Ok(Self::Bool(false));
+                    }
+                }
+
+                Ok(Self::Bool(true))
+            }
+            // `cmp(a, b) == '<'`
+            (Self::Ordering(lhs), Self::String(rhs)) | (Self::String(rhs), Self::Ordering(lhs)) => {
+                Ok(Self::Bool(lhs.to_string() == rhs))
+            }
 
             (lhs, rhs) => Err(format!(
                 "cannot test equality `{} == {}`",
@@ -81,6 +434,13 @@ impl<'a> Literal<'a> {
             (Self::Bool(lhs), Self::Bool(rhs)) => Ok(Self::Bool(lhs != rhs)),
             (Self::String(lhs), Self::String(rhs)) => Ok(Self::Bool(lhs != rhs)),
             (Self::Integer(lhs), Self::Integer(rhs)) => Ok(Self::Bool(lhs != rhs)),
+            (Self::Ordering(lhs), Self::Ordering(rhs)) => Ok(Self::Bool(lhs != rhs)),
+            (Self::Ordering(lhs), Self::String(rhs)) | (Self::String(rhs), Self::Ordering(lhs)) => {
+                Ok(Self::Bool(lhs.to_string() != rhs))
+            }
+            (lhs @ Self::Array(..), rhs @ Self::Array(..))
+            | (lhs @ Self::None, rhs)
+            | (lhs, rhs @ Self::None) => lhs.req(rhs).and_then(Not::not),
             (lhs, rhs) => Err(format!(
                 "cannot test inequality `{} != {}`",
                 lhs.type_of(),
@@ -89,60 +449,108 @@ impl<'a> Literal<'a> {
         }
     }
 
-    pub fn rgt(self, rhs: Self) -> Result<Self, String> {
+    /// Orders two values of the same type. Strings compare lexicographically, `false < true`, and
+    /// arrays element by element, the shorter one first if one is a prefix of the other.
+    pub fn ordering(&self, rhs: &Self) -> Result<Ordering, String> {
         match (self, rhs) {
-            // (Self::Bool(lhs), Self::Bool(rhs)) => Ok(Self::Bool(lhs > rhs)),
-            // (Self::String(lhs), Self::String(rhs)) => Ok(Self::Bool(lhs > rhs)),
-            (Self::Integer(lhs), Self::Integer(rhs)) => Ok(Self::Bool(lhs > rhs)),
+            (Self::Integer(lhs), Self::Integer(rhs)) => Ok(lhs.cmp(rhs)),
+            (Self::String(lhs), Self::String(rhs)) => Ok(lhs.cmp(rhs)),
+            (Self::Bool(lhs), Self::Bool(rhs)) => Ok(lhs.cmp(rhs)),
+            (Self::Array(lhs, _), Self::Array(rhs, _)) => {
+                for (l, r) in lhs.iter().zip(rhs) {
+                    match compare_once(l, r, Ordering::Equal, Self::ordering)? {
+                        Ordering::Equal => {}
+                        o => return Ok(o),
+                    }
+                }
+
+                Ok(lhs.len().cmp(&rhs.len()))
+            }
             (lhs, rhs) => Err(format!(
-                "cannot test inequality `{} > {}`",
+                "cannot compare `{}` with `{}`",
                 lhs.type_of(),
                 rhs.type_of()
             )),
         }
     }
 
-    pub fn rge(self, rhs: Self) -> Result<Self, String> {
-        match (self, rhs) {
-            // (Self::Bool(lhs), Self::Bool(rhs)) => Ok(Self::Bool(lhs >= rhs)),
-            // (Self::String(lhs), Self::String(rhs)) => Ok(Self::Bool(lhs >= rhs)),
-            (Self::Integer(lhs), Self::Integer(rhs)) => Ok(Self::Bool(lhs >= rhs)),
-            (lhs, rhs) => Err(format!(
-                "cannot test inequality `{} >= {}`",
-                lhs.type_of(),
-                rhs.type_of()
-            )),
-        }
+    pub fn rcmp(&self, rhs: &Self) -> Result<Self, String> {
+        self.ordering(rhs).map(|x| Self::Ordering(x.into()))
     }
 
-    pub fn rlt(self, rhs: Self) -> Result<Self, String> {
-        match (self, rhs) {
-            // (Self::Bool(lhs), Self::Bool(rhs)) => Ok(Self::Bool(lhs < rhs)),
-            // (Self::String(lhs), Self::String(rhs)) => Ok(Self::Bool(lhs < rhs)),
-            (Self::Integer(lhs), Self::Integer(rhs)) => Ok(Self::Bool(lhs < rhs)),
-            (lhs, rhs) => Err(format!(
-                "cannot test inequality `{} < {}`",
+    /// Like [`Self::rcmp`], but values at most `epsilon` apart are `AlmostEqual`.
+    pub fn rcmp_within(self, epsilon: Self, rhs: Self) -> Result<Self, String> {
+        match (self, epsilon, rhs) {
+            (Self::Integer(..), Self::Integer(e), Self::Integer(..)) if e < 0 => {
+                Err(format!("epsilon cannot be negative, but is {e}"))
+            }
+            (Self::Integer(lhs), Self::Integer(e), Self::Integer(rhs)) => {
+                Ok(Self::Ordering(match lhs.abs_diff(rhs) {
+                    0 => TotalOrdering::Equal,
+                    d if d <= e.unsigned_abs() => TotalOrdering::AlmostEqual,
+                    _ if lhs > rhs => TotalOrdering::GreaterThan,
+                    _ => TotalOrdering::LessThan,
+                }))
+            }
+            (lhs, e, rhs) => Err(format!(
+                "cannot compare `{}` with `{}` within `{}`",
                 lhs.type_of(),
-                rhs.type_of()
+                rhs.type_of(),
+                e.type_of()
             )),
         }
     }
 
-    pub fn rle(self, rhs: Self) -> Result<Self, String> {
-        match (self, rhs) {
-            // (Self::Bool(lhs), Self::Bool(rhs)) => Ok(Self::Bool(lhs <= rhs)),
-            // (Self::String(lhs), Self::String(rhs)) => Ok(Self::Bool(lhs <= rhs)),
-            (Self::Integer(lhs), Self::Integer(rhs)) => Ok(Self::Bool(lhs <= rhs)),
-            (lhs, rhs) => Err(format!(
-                "cannot test inequality `{} <= {}`",
-                lhs.type_of(),
-                rhs.type_of()
-            )),
-        }
+    pub fn rgt(&self, rhs: &Self) -> Result<Self, String> {
+        self.ordering(rhs)
+            .map(|x| Self::Bool(x.is_gt()))
+            .map_err(|_| {
+                format!(
+                    "cannot test inequality `{} > {}`",
+                    self.type_of(),
+                    rhs.type_of()
+                )
+            })
+    }
+
+    pub fn rge(&self, rhs: &Self) -> Result<Self, String> {
+        self.ordering(rhs)
+            .map(|x| Self::Bool(x.is_ge()))
+            .map_err(|_| {
+                format!(
+                    "cannot test inequality `{} >= {}`",
+                    self.type_of(),
+                    rhs.type_of()
+                )
+            })
+    }
+
+    pub fn rlt(&self, rhs: &Self) -> Result<Self, String> {
+        self.ordering(rhs)
+            .map(|x| Self::Bool(x.is_lt()))
+            .map_err(|_| {
+                format!(
+                    "cannot test inequality `{} < {}`",
+                    self.type_of(),
+                    rhs.type_of()
+                )
+            })
+    }
+
+    pub fn rle(&self, rhs: &Self) -> Result<Self, String> {
+        self.ordering(rhs)
+            .map(|x| Self::Bool(x.is_le()))
+            .map_err(|_| {
+                format!(
+                    "cannot test inequality `{} <= {}`",
+                    self.type_of(),
+                    rhs.type_of()
+                )
+            })
     }
 }
 
-impl<'a> Add for Literal<'a> {
+impl Add for Literal<'_> {
     type Output = Result<Self, String>;
     fn add(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
@@ -157,7 +565,7 @@ impl<'a> Add for Literal<'a> {
     }
 }
 
-impl<'a> Sub for Literal<'a> {
+impl Sub for Literal<'_> {
     type Output = Result<Self, String>;
     fn sub(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
@@ -172,7 +580,7 @@ impl<'a> Sub for Literal<'a> {
     }
 }
 
-impl<'a> Mul for Literal<'a> {
+impl Mul for Literal<'_> {
     type Output = Result<Self, String>;
     fn mul(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
@@ -187,7 +595,7 @@ impl<'a> Mul for Literal<'a> {
     }
 }
 
-impl<'a> Div for Literal<'a> {
+impl Div for Literal<'_> {
     type Output = Result<Self, String>;
     fn div(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
@@ -208,7 +616,7 @@ impl<'a> Div for Literal<'a> {
     }
 }
 
-impl<'a> Rem for Literal<'a> {
+impl Rem for Literal<'_> {
     type Output = Result<Self, String>;
     fn rem(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
@@ -229,7 +637,7 @@ impl<'a> Rem for Literal<'a> {
     }
 }
 
-impl<'a> Shl for Literal<'a> {
+impl Shl for Literal<'_> {
     type Output = Result<Self, String>;
     fn shl(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
@@ -244,7 +652,7 @@ impl<'a> Shl for Literal<'a> {
     }
 }
 
-impl<'a> Shr for Literal<'a> {
+impl Shr for Literal<'_> {
     type Output = Result<Self, String>;
     fn shr(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
@@ -259,7 +667,7 @@ impl<'a> Shr for Literal<'a> {
     }
 }
 
-impl<'a> BitAnd for Literal<'a> {
+impl BitAnd for Literal<'_> {
     type Output = Result<Self, String>;
     fn bitand(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
@@ -275,7 +683,7 @@ impl<'a> BitAnd for Literal<'a> {
     }
 }
 
-impl<'a> BitOr for Literal<'a> {
+impl BitOr for Literal<'_> {
     type Output = Result<Self, String>;
     fn bitor(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
@@ -291,7 +699,7 @@ impl<'a> BitOr for Literal<'a> {
     }
 }
 
-impl<'a> BitXor for Literal<'a> {
+impl BitXor for Literal<'_> {
     type Output = Result<Self, String>;
     fn bitxor(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
@@ -307,24 +715,24 @@ impl<'a> BitXor for Literal<'a> {
     }
 }
 
-impl<'a> Neg for Literal<'a> {
+impl Neg for Literal<'_> {
     type Output = Result<Self, String>;
     fn neg(self) -> Self::Output {
         match self {
             Self::Integer(lhs) => Ok(Self::Integer(-lhs)),
 
-            lhs => Err(format!("cannot negate `-{}`", lhs.type_of(),)),
+            lhs => Err(format!("cannot negate `-{}`", lhs.type_of())),
         }
     }
 }
 
-impl<'a> Not for Literal<'a> {
+impl Not for Literal<'_> {
     type Output = Result<Self, String>;
     fn not(self) -> Self::Output {
         match self {
             Self::Bool(lhs) => Ok(Self::Bool(!lhs)),
 
-            lhs => Err(format!("cannot get inverse `!{}`", lhs.type_of(),)),
+            lhs => Err(format!("cannot get inverse `!{}`", lhs.type_of())),
         }
     }
 }
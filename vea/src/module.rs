@@ -0,0 +1,183 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    rc::Rc,
+};
+
+use chumsky::prelude::*;
+
+use crate::{
+    ast::Expr,
+    common::Tag,
+    env::Env,
+    interpreter::exec,
+    lexer::{lexer, Token},
+    literal::Literal,
+    parser::parse_at,
+    resolver::resolve,
+    span::{RawSpan, Span},
+    stdlib,
+    symbol::Symbol,
+};
+
+/// Modules that `use std::...` finds without looking at the search path.
+const STD: &[(&str, &str)] = &[("option", include_str!("../../std/option.vea"))];
+
+/// A module's source, with the place its tokens start at.
+///
+/// Spans are indices into a token list, so every loaded file gets its own range of indices after
+/// the main file's. This way an error raised inside a module can still be traced back to it.
+#[derive(Debug, Clone)]
+pub struct File {
+    pub name: String,
+    pub src: String,
+    pub tokens: Vec<Span<Token>>,
+    pub offset: usize,
+}
+
+/// Finds, loads and caches the modules of a program.
+#[derive(Debug, Clone, Default)]
+pub struct Modules<'a> {
+    /// Directories searched for `use a::b;` as `a/b.vea`, in order.
+    pub search_path: Vec<PathBuf>,
+    /// Every module loaded so far.
+    pub files: Vec<File>,
+    /// The number of tokens in the main file.
+    pub base: usize,
+    cache: HashMap<String, Rc<RefCell<Literal<'a>>>>,
+    loading: Vec<String>,
+}
+
+impl Modules<'_> {
+    #[must_use]
+    pub fn new(search_path: Vec<PathBuf>) -> Self {
+        Self {
+            search_path,
+            ..Self::default()
+        }
+    }
+
+    /// The file that token `index` belongs to, or `None` for the main file.
+    #[must_use]
+    pub fn file(&self, index: usize) -> Option<&File> {
+        self.files
+            .iter()
+            .find(|f| (f.offset..f.offset + f.tokens.len()).contains(&index))
+    }
+
+    /// Where the tokens of the next file loaded start.
+    #[must_use]
+    pub fn next_offset(&self) -> usize {
+        self.files
+            .last()
+            .map_or(self.base, |f| f.offset + f.tokens.len())
+    }
+
+    /// The source of the module at `path`, from the first directory of the search path that has it.
+    fn find(&self, path: &[Span<Symbol>]) -> Option<String> {
+        if let [Span(std, _), Span(module, _)] = path {
+            if let Some((_, src)) = STD.iter().find(|(n, _)| **std == *"std" && n == &&**module) {
+                return Some((*src).to_owned());
+            }
+        }
+
+        for dir in &self.search_path {
+            let mut file = dir.clone();
+            file.extend(path.iter().map(|x| &*x.0));
+            file.set_extension("vea");
+
+            if let Ok(src) = std::fs::read_to_string(&file) {
+                return Some(src);
+            }
+        }
+
+        None
+    }
+}
+
+/// Loads the module at `path`, returning an object holding its top-level bindings.
+pub fn load<'a>(
+    program: &Env<'a>,
+    path: &[Span<Symbol>],
+    span: RawSpan,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    let modules = program.modules.clone();
+
+    // modules written in Rust are fresh objects, so they need no caching
+    if let [Span(std, _), Span(native, _)] = path {
+        if let Some(module) = (**std == *"std").then(|| stdlib::module(native)).flatten() {
+            return Ok(Rc::new(RefCell::new(module)));
+        }
+    }
+
+    let name = path.iter().map(|x| &*x.0).collect::<Vec<_>>().join("::");
+
+    if let Some(module) = modules.borrow().cache.get(&name) {
+        return Ok(module.clone());
+    }
+
+    if modules.borrow().loading.contains(&name) {
+        let mut cycle = modules.borrow().loading.clone();
+        cycle.push(name);
+
+        return Err(format!("import cycle: {}", cycle.join(" -> ")).t(span));
+    }
+
+    let src = modules
+        .borrow()
+        .find(path)
+        .ok_or_else(|| format!("cannot find module `{name}`").t(span))?;
+
+    let (tokens, errors) = lexer().parse(&src).into_output_errors();
+
+    if let Some(e) = errors.first() {
+        return Err(format!("cannot lex module `{name}`: {}", e.reason()).t(span));
+    }
+
+    let tokens = tokens.unwrap_or_default();
+    let offset = modules.borrow().next_offset();
+
+    modules.borrow_mut().files.push(File {
+        name: name.clone(),
+        src,
+        tokens: tokens.clone(),
+        offset,
+    });
+
+    let tokens = tokens.into_iter().map(|x| x.0).collect::<Vec<_>>();
+    let (exprs, errors) = parse_at(&tokens, offset);
+
+    if let Some(e) = errors.first() {
+        return Err(format!("cannot parse module `{name}`: {}", e.reason()).t(*e.span()));
+    }
+
+    let exprs = exprs.unwrap_or_default();
+
+    let env = Rc::new(RefCell::new(Env::sharing(None, program)));
+
+    modules.borrow_mut().loading.push(name.clone());
+    let resolved = resolve(&exprs, &env.borrow());
+    let result = resolved.and_then(|()| exec(exprs.clone(), &env));
+    modules.borrow_mut().loading.pop();
+    result?;
+
+    let mut exports = HashMap::new();
+
+    for Span(expr, _) in &exprs {
+        let ident = match expr {
+            Expr::Let { ident, .. } => ident,
+            Expr::FnDecl { name, .. } => name,
+            _ => continue,
+        };
+
+        if let Some(value) = env.borrow().get(&ident.0) {
+            exports.insert(ident.0.clone(), value);
+        }
+    }
+
+    let module = Rc::new(RefCell::new(Literal::Object(exports, HashSet::new())));
+    modules.borrow_mut().cache.insert(name, module.clone());
+
+    Ok(module)
+}
@@ -0,0 +1,116 @@
+//! Where `print`, `println` and `eprint()` write to.
+
+use std::{
+    fmt::{self, Debug},
+    io::{self, Write},
+    mem,
+};
+
+/// A destination for the output of a program, shared by every scope of it.
+pub trait Output: Debug {
+    fn write(&mut self, text: &str) -> io::Result<()>;
+
+    /// Passes on anything still held back, e.g. a line that has not ended yet.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Everything written so far that has not been taken yet, for outputs that keep it.
+    fn take(&mut self) -> String {
+        String::new()
+    }
+}
+
+/// Output kept in a string, for whoever ran the program to take afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct Buffer(String);
+
+impl Output for Buffer {
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.0 += text;
+        Ok(())
+    }
+
+    fn take(&mut self) -> String {
+        mem::take(&mut self.0)
+    }
+}
+
+/// Output written through as it happens, e.g. to the standard output of this process.
+pub struct Stream<W: Write>(pub W);
+
+impl Stream<io::Stdout> {
+    #[must_use]
+    pub fn stdout() -> Self {
+        Self(io::stdout())
+    }
+}
+
+impl Stream<io::Stderr> {
+    #[must_use]
+    pub fn stderr() -> Self {
+        Self(io::stderr())
+    }
+}
+
+impl<W: Write> Debug for Stream<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stream").finish_non_exhaustive()
+    }
+}
+
+impl<W: Write> Output for Stream<W> {
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.0.write_all(text.as_bytes())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Output given to a callback one line at a time, without its line ending.
+pub struct Lines<F: FnMut(&str)> {
+    f: F,
+    line: String,
+}
+
+impl<F: FnMut(&str)> Lines<F> {
+    pub const fn new(f: F) -> Self {
+        Self {
+            f,
+            line: String::new(),
+        }
+    }
+}
+
+impl<F: FnMut(&str)> Debug for Lines<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lines")
+            .field("line", &self.line)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F: FnMut(&str)> Output for Lines<F> {
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        for part in text.split_inclusive('\n') {
+            self.line += part;
+
+            if let Some(line) = self.line.strip_suffix('\n') {
+                (self.f)(line);
+                self.line.clear();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            (self.f)(&mem::take(&mut self.line));
+        }
+
+        Ok(())
+    }
+}
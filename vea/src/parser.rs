@@ -1,28 +1,36 @@
+use chumsky::input::ValueInput;
 use chumsky::prelude::*;
 use chumsky::primitive::select;
 
+use crate::ast::Constant;
 use crate::ast::Expr;
+use crate::ast::Pattern;
 
 use crate::choice;
 use crate::common::Tag;
-use crate::literal::Literal;
 use crate::span::RawSpan;
 use crate::span::Span;
 
 use crate::lexer::Token;
 
 #[must_use]
-pub fn parser<'t, 's: 't>() -> impl Parser<
-    't,
-    &'t [Token<'s>],
-    Vec<Span<Expr<'s>>>,
-    chumsky::extra::Err<Rich<'t, Token<'s>, RawSpan>>,
-> + Clone {
+pub fn parser<'t, 's: 't, I>(
+) -> impl Parser<'t, I, Vec<Span<Expr>>, chumsky::extra::Err<Rich<'t, Token, RawSpan>>> + Clone
+where
+    I: ValueInput<'t, Token = Token, Span = RawSpan>,
+{
     let ident = select(move |f, s| match f {
         Token::Ident(t) => Some(Span(t, s)),
         _ => None,
     });
 
+    // `self` is a keyword, but objects take it as their first argument, as in `fn cmp(self, other)`
+    let argument = select(move |f, s| match f {
+        Token::Ident(t) => Some(Span(t, s)),
+        Token::This => Some(Span("self".into(), s)),
+        _ => None,
+    });
+
     let number = select(move |f, s| match f {
         Token::Number(t) => Some(Span(t, s)),
         _ => None,
@@ -44,16 +52,33 @@ pub fn parser<'t, 's: 't>() -> impl Parser<
             .boxed();
 
         let kwfn = group((
+            // `test` is only special right before `fn`
+            just(Token::Ident("test".into()))
+                .map_with_span(Span)
+                .or_not(),
+            just(Token::Unsafe).map_with_span(Span).or_not(),
             just(Token::Fn).map_with_span(Span),
             ident,
             just(Token::LeftParen).map_with_span(Span),
-            ident.separated_by(just(Token::Comma)).collect(),
+            argument.separated_by(just(Token::Comma)).collect(),
             just(Token::RightParen).map_with_span(Span),
             block.clone(),
         ))
         .map_with_span(
-            |(fn_token, name, left_paren, arguments, right_paren, block), s| {
+            |(
+                test_token,
+                unsafe_token,
+                fn_token,
+                name,
+                left_paren,
+                arguments,
+                right_paren,
+                block,
+            ),
+             s| {
                 Expr::FnDecl {
+                    test_token,
+                    unsafe_token,
                     fn_token,
                     name,
                     left_paren,
@@ -66,6 +91,45 @@ pub fn parser<'t, 's: 't>() -> impl Parser<
         )
         .boxed();
 
+        let method = group((
+            just(Token::Unsafe).map_with_span(Span).or_not(),
+            just(Token::This)
+                .map_with_span(Span)
+                .then_ignore(just(Token::DoubleColon))
+                .or_not(),
+            ident,
+            just(Token::LeftParen).map_with_span(Span),
+            argument.separated_by(just(Token::Comma)).collect(),
+            just(Token::RightParen).map_with_span(Span),
+            block.clone(),
+        ))
+        .map_with_span(
+            |(unsafe_token, self_token, name, left_paren, arguments, right_paren, block), s| {
+                Expr::Method {
+                    unsafe_token,
+                    self_token,
+                    name,
+                    left_paren,
+                    arguments,
+                    right_paren,
+                    block: Box::new(block),
+                }
+                .t(s)
+            },
+        )
+        .boxed();
+
+        let kwunsafe = just(Token::Unsafe)
+            .map_with_span(Span)
+            .then(block.clone())
+            .map_with_span(|(unsafe_token, block), s| {
+                Expr::Unsafe {
+                    unsafe_token,
+                    block: Box::new(block),
+                }
+                .t(s)
+            });
+
         macro_rules! kwlet {
             ($s:ident) => {
                 just(Token::Let)
@@ -103,50 +167,16 @@ pub fn parser<'t, 's: 't>() -> impl Parser<
             })
             .boxed();
 
-            // TODO: fix that
-            let idx = group((
-                eel.clone(),
-                just(Token::LeftBracket),
-                eel.clone(),
-                just(Token::RightBracket),
-            ))
-            .map_with_span(|(parent, _, child, _), s| Expr::Chain { parent, child }.t(s));
-
-            let atom = select(move |f, s| match f {
-                Token::Ident(t) => Some(Expr::Access { ident: t.t(s) }),
-                Token::Number(t) => Some(Expr::Literal {
-                    value: Literal::Integer(t),
-                }),
-                Token::Underscore => Some(Expr::Literal {
-                    value: Literal::None,
-                }),
-                Token::String(t) => Some(Expr::Literal {
-                    value: Literal::String(t),
-                }),
-                Token::True => Some(Expr::Literal {
-                    value: Literal::Bool(true),
-                }),
-                Token::False => Some(Expr::Literal {
-                    value: Literal::Bool(false),
-                }),
-
-                _ => None,
-            })
-            .map_with_span(Span)
-            .or(idx.clone())
-            .or(kgroup)
-            .boxed();
-
             let imul = group((number, ident)).map_with_span(|(n, v), s| {
                 Expr::Mul {
                     star_token: Span(Token::Star, SimpleSpan::new(n.1.end, v.1.start)),
                     lhs: Box::new(Span(
                         Expr::Literal {
-                            value: Literal::Integer(n.0),
+                            value: Constant::Integer(n.0),
                         },
                         n.1,
                     )),
-                    rhs: Box::new(Span(Expr::Access { ident: v }, v.1)),
+                    rhs: Box::new(Span(Expr::Access { ident: v.clone() }, v.1)),
                 }
                 .t(s)
             });
@@ -189,79 +219,409 @@ pub fn parser<'t, 's: 't>() -> impl Parser<
             })
             .boxed();
 
-            macro_rules! op {
-                (1 $token:path, $expr:ident, $op:ident, $e: expr) => {
-                    group((just($token).map_with_span(Span), atom.clone()))
-                        .map(|($op, $expr)| ($op, Box::new($expr)))
-                        .map_with_span(|($op, $expr), s| $e.t(s))
-                        .boxed()
-                };
-                (2 $token:path, $lhs:ident $op:ident $rhs:ident, $e:expr) => {
-                    group((atom.clone(), just($token).map_with_span(Span), atom.clone()))
-                        .map(|($lhs, $op, $rhs)| (Box::new($lhs), $op, Box::new($rhs)))
-                        .map_with_span(|($lhs, $op, $rhs), s| $e.t(s))
-                        .boxed()
-                };
-            }
-
-            let unary = choice![
-                op![1 Token::Bang, expr, bang_token, Expr::Not { bang_token, expr }],
-                op![1 Token::Minus, expr, minus_token, Expr::Neg { minus_token, expr }],
-            ]
+            let array = group((
+                just(Token::LeftBracket).map_with_span(Span),
+                eel.clone()
+                    .separated_by(just(Token::Comma))
+                    .allow_trailing()
+                    .collect::<Vec<_>>(),
+                just(Token::RightBracket).map_with_span(Span),
+            ))
+            .map_with_span(|(left_bracket, exprs, right_bracket), s| {
+                Expr::Array {
+                    left_bracket,
+                    exprs: exprs.into_iter().map(|x| *x).collect(),
+                    right_bracket,
+                }
+                .t(s)
+            })
             .boxed();
 
-            let sum = choice![
-                op![2 Token::Plus, lhs plus_token rhs, Expr::Add { lhs, plus_token, rhs }],
-                op![2 Token::Minus, lhs minus_token rhs, Expr::Sub { lhs, minus_token, rhs }],
-            ]
-            .boxed();
+            // the tokens of `'a {b} c {d:x} e'`
+            let interp = group((
+                select(|f, s| match f {
+                    Token::InterpStart(_) => Some(Span(f, s)),
+                    _ => None,
+                }),
+                group((
+                    eel.clone(),
+                    select(|f, s| match f {
+                        Token::FormatSpec(_) => Some(Span(f, s)),
+                        _ => None,
+                    })
+                    .or_not(),
+                    select(|f, s| match f {
+                        Token::InterpMid(_) | Token::InterpEnd(_) => Some(Span(f, s)),
+                        _ => None,
+                    }),
+                ))
+                .repeated()
+                .at_least(1)
+                .collect::<Vec<_>>(),
+            ))
+            .try_map(|(start, holes), s| {
+                // only the last hole can be followed by the end of the string
+                let ends = holes
+                    .iter()
+                    .position(|(_, _, text)| matches!(text.0, Token::InterpEnd(_)));
 
-            let product = choice![
-                op![2 Token::Star, lhs star_token rhs, Expr::Mul { lhs, star_token, rhs }],
-                op![2 Token::Slash, lhs slash_token rhs, Expr::Div { lhs, slash_token, rhs }],
-                op![2 Token::Percent, lhs percent_token rhs, Expr::Rem { lhs, percent_token, rhs }],
-            ]
-            .boxed();
+                if ends != Some(holes.len() - 1) {
+                    return Err(Rich::custom(s, "malformed interpolated string"));
+                }
 
-            let cmp = choice![
-                op![2 Token::EqEq, lhs eqeq_token rhs, Expr::Eq { lhs, eqeq_token, rhs }],
-                op![2 Token::Ne, lhs ne_token rhs, Expr::Ne { lhs, ne_token, rhs }],
-                op![2 Token::Gt, lhs gt_token rhs, Expr::Gt { lhs, gt_token, rhs }],
-                op![2 Token::Ge, lhs ge_token rhs, Expr::Ge { lhs, ge_token, rhs }],
-                op![2 Token::Lt, lhs lt_token rhs, Expr::Lt { lhs, lt_token, rhs }],
-                op![2 Token::Le, lhs le_token rhs, Expr::Le { lhs, le_token, rhs }],
-            ]
+                Ok(Expr::Interp {
+                    start,
+                    holes: holes
+                        .into_iter()
+                        .map(|(expr, spec, text)| (*expr, spec, text))
+                        .collect(),
+                }
+                .t(s))
+            })
             .boxed();
 
-            let bitwise = choice![
-                op![2 Token::And, lhs and_token rhs, Expr::And { lhs, and_token, rhs }],
-                op![2 Token::Caret, lhs caret_token rhs, Expr::Xor { lhs, caret_token, rhs }],
-                op![2 Token::Pipe, lhs pipe_token rhs, Expr::Or { lhs, pipe_token, rhs }],
-            ]
-            .boxed();
+            let atom = imul
+                .or(interp)
+                .or(select(move |f, s| match f {
+                    Token::Ident(t) => Some(Expr::Access { ident: t.t(s) }),
+                    Token::Number(t) => Some(Expr::Literal {
+                        value: Constant::Integer(t),
+                    }),
+                    Token::Underscore => Some(Expr::Empty {
+                        underscore_token: Span(Token::Underscore, s),
+                    }),
+                    Token::This => Some(Expr::This {
+                        self_token: Span(Token::This, s),
+                    }),
+                    Token::String(t) => Some(Expr::Literal {
+                        value: Constant::String(t),
+                    }),
+                    Token::True => Some(Expr::Literal {
+                        value: Constant::Bool(true),
+                    }),
+                    Token::False => Some(Expr::Literal {
+                        value: Constant::Bool(false),
+                    }),
+
+                    _ => None,
+                })
+                .map_with_span(Span))
+                .or(kgroup.clone())
+                .or(obj)
+                .or(set)
+                .or(array)
+                .boxed();
 
-            let fncall = group((
-                ident.map_with_span(|ident, s| Box::new(Expr::Access { ident }.t(s))),
+            // `f(...)` and `x[...]` bind tighter than any operator
+            let call = group((
                 just(Token::LeftParen).map_with_span(Span),
                 eel.clone()
                     .separated_by(just(Token::Comma))
                     .allow_trailing()
                     .collect::<Vec<_>>()
-                    .map(|x| x.iter().map(|x| *x.clone()).collect::<Vec<_>>()),
+                    .map(|x| x.into_iter().map(|x| *x).collect::<Vec<_>>()),
                 just(Token::RightParen).map_with_span(Span),
             ))
-            .map_with_span(|(access, left_paren, arguments, right_paren), s| {
-                Expr::FnCall {
-                    access,
-                    arguments,
-                    left_paren,
-                    right_paren,
-                }
-                .t(s)
-            })
-            .boxed();
+            .map(|(left_paren, arguments, right_paren)| Postfix::Call {
+                left_paren,
+                arguments,
+                right_paren,
+            });
+
+            // `x[a..b]`, `x[a..=b]`, and either end can be left out
+            let slice = group((
+                just(Token::LeftBracket),
+                eel.clone().or_not(),
+                just(Token::Period),
+                just(Token::Period),
+                just(Token::Eq).or_not(),
+                eel.clone().or_not(),
+                just(Token::RightBracket),
+            ))
+            .map(|(_, start, _, _, eq, end, _)| Postfix::Slice {
+                start,
+                end,
+                inclusive: eq.is_some(),
+            });
+
+            let index = just(Token::LeftBracket)
+                .ignore_then(eel.clone())
+                .then_ignore(just(Token::RightBracket))
+                .map(|child| Postfix::Index { child });
+
+            // `a::b` is `a['b']`
+            let path = just(Token::DoubleColon)
+                .ignore_then(ident)
+                .map(|Span(ident, s)| Postfix::Index {
+                    child: Box::new(
+                        Expr::Literal {
+                            value: Constant::String(ident.to_string()),
+                        }
+                        .t(s),
+                    ),
+                });
+
+            let restrict = group((
+                just(Token::LeftBrace).map_with_span(Span),
+                eel.clone(),
+                just(Token::RightBrace).map_with_span(Span),
+            ))
+            .map(|(left_brace, pred, right_brace)| Postfix::Restrict {
+                left_brace,
+                pred,
+                right_brace,
+            });
+
+            let postfix = atom
+                .foldl(
+                    call.or(slice)
+                        .or(index)
+                        .or(path)
+                        .or(restrict)
+                        .map_with_span(Span)
+                        .repeated(),
+                    |lhs, Span(op, s)| {
+                        let span = SimpleSpan::new(lhs.1.start, s.end);
+
+                        match op {
+                            Postfix::Call {
+                                left_paren,
+                                arguments,
+                                right_paren,
+                            } => Expr::FnCall {
+                                access: Box::new(lhs),
+                                left_paren,
+                                arguments,
+                                right_paren,
+                            },
+                            Postfix::Index { child } => Expr::Chain {
+                                parent: Box::new(lhs),
+                                child,
+                            },
+                            Postfix::Slice {
+                                start,
+                                end,
+                                inclusive,
+                            } => Expr::Slice {
+                                parent: Box::new(lhs),
+                                start,
+                                end,
+                                inclusive,
+                            },
+                            Postfix::Restrict {
+                                left_brace,
+                                pred,
+                                right_brace,
+                            } => Expr::Restrict {
+                                expr: Box::new(lhs),
+                                left_brace,
+                                pred,
+                                right_brace,
+                            },
+                        }
+                        .t(span)
+                    },
+                )
+                .boxed();
+
+            let unary = choice![just(Token::Bang), just(Token::Minus), just(Token::And)]
+                .map_with_span(Span)
+                .repeated()
+                .foldr(postfix, |op, expr| {
+                    let span = SimpleSpan::new(op.1.start, expr.1.end);
+                    let expr = Box::new(expr);
+
+                    match op.0 {
+                        Token::Bang => Expr::Not {
+                            bang_token: op,
+                            expr,
+                        },
+                        Token::Minus => Expr::Neg {
+                            minus_token: op,
+                            expr,
+                        },
+                        _ => Expr::Copy {
+                            and_token: op,
+                            expr,
+                        },
+                    }
+                    .t(span)
+                })
+                .boxed();
+
+            macro_rules! binary {
+                ($operand:expr, $($token:path),+) => {
+                    $operand
+                        .clone()
+                        .foldl(
+                            choice![$(just($token),)+]
+                                .map_with_span(Span)
+                                .then($operand.clone())
+                                .repeated(),
+                            |lhs, (op, rhs)| binary(lhs, op, rhs),
+                        )
+                        .boxed()
+                };
+            }
+
+            let product = binary!(unary, Token::Star, Token::Slash, Token::Percent);
+            let sum = binary!(product, Token::Plus, Token::Minus);
+            let shift = binary!(sum, Token::Shl, Token::Shr);
+            let bitand = binary!(shift, Token::And);
+            let bitxor = binary!(bitand, Token::Caret);
+            let bitor = binary!(bitxor, Token::Pipe);
+            // `x ==~2 y` and `x ==~(n + 1) y`, so that the epsilon never takes `2 y` for `2 * y`
+            let epsilon = number
+                .map_with_span(|n, s| {
+                    Expr::Literal {
+                        value: Constant::Integer(n.0),
+                    }
+                    .t(s)
+                })
+                .or(kgroup)
+                .boxed();
+            let approx = bitor
+                .clone()
+                .foldl(
+                    choice![
+                        just(Token::GtTilde),
+                        just(Token::GeTilde),
+                        just(Token::LtTilde),
+                        just(Token::LeTilde),
+                        just(Token::EqEqTilde),
+                    ]
+                    .map_with_span(Span)
+                    .then(epsilon)
+                    .then(bitor.clone())
+                    .repeated(),
+                    |lhs, ((op, epsilon), rhs)| approx(lhs, op, epsilon, rhs),
+                )
+                .boxed();
+            let cmp = binary!(
+                approx,
+                Token::EqEq,
+                Token::Ne,
+                Token::Gt,
+                Token::Ge,
+                Token::Lt,
+                Token::Le
+            );
+
+            let pattern = recursive(|pat| {
+                let int = just(Token::Minus).or_not().then(number).map(|(minus, n)| {
+                    if minus.is_some() {
+                        -n.0
+                    } else {
+                        n.0
+                    }
+                });
+
+                let range = group((
+                    int.clone(),
+                    just(Token::Period),
+                    just(Token::Period),
+                    just(Token::Eq).or_not(),
+                    int.clone(),
+                ))
+                .map(|(start, _, _, eq, end)| Pattern::Range {
+                    start,
+                    end,
+                    inclusive: eq.is_some(),
+                });
+
+                let single = int
+                    .map(|x| Pattern::Literal {
+                        value: Constant::Integer(x),
+                    })
+                    .or(select(move |f, s| match f {
+                        Token::Underscore => Some(Pattern::Wildcard),
+                        Token::Ident(t) => Some(Pattern::Ident { ident: t.t(s) }),
+                        Token::String(t) => Some(Pattern::Literal {
+                            value: Constant::String(t),
+                        }),
+                        Token::True => Some(Pattern::Literal {
+                            value: Constant::Bool(true),
+                        }),
+                        Token::False => Some(Pattern::Literal {
+                            value: Constant::Bool(false),
+                        }),
+
+                        _ => None,
+                    }));
 
-            choice![fncall, obj, set, bitwise, cmp, product, sum, unary, imul, atom].map(Box::new)
+                let value = just(Token::LeftParen)
+                    .ignore_then(eel.clone())
+                    .then_ignore(just(Token::RightParen))
+                    .map(|expr| Pattern::Value { expr });
+
+                let object = just(Token::Struct)
+                    .ignore_then(just(Token::LeftBrace))
+                    .ignore_then(
+                        ident
+                            .then(just(Token::Colon).ignore_then(pat.clone()).or_not())
+                            .map(|(k, p)| (k.clone(), p.unwrap_or(Span(Pattern::Wildcard, k.1))))
+                            .separated_by(just(Token::Comma))
+                            .allow_trailing()
+                            .collect::<Vec<_>>(),
+                    )
+                    .then_ignore(just(Token::RightBrace))
+                    .map(|fields| Pattern::Object { fields });
+
+                let set = just(Token::Set)
+                    .ignore_then(just(Token::LeftBrace))
+                    .ignore_then(
+                        pat.clone()
+                            .separated_by(just(Token::Comma))
+                            .allow_trailing()
+                            .collect::<Vec<_>>(),
+                    )
+                    .then_ignore(just(Token::RightBrace))
+                    .map(|items| Pattern::Set { items });
+
+                let array = just(Token::LeftBracket)
+                    .ignore_then(
+                        pat.clone()
+                            .separated_by(just(Token::Comma))
+                            .allow_trailing()
+                            .collect::<Vec<_>>(),
+                    )
+                    .then_ignore(just(Token::RightBracket))
+                    .map(|items| Pattern::Array { items });
+
+                choice![range, object, set, array, value, single]
+                    .map_with_span(Span)
+                    .separated_by(just(Token::Pipe))
+                    .at_least(1)
+                    .collect::<Vec<_>>()
+                    .map_with_span(|mut alternatives, s| {
+                        if alternatives.len() == 1 {
+                            alternatives.remove(0)
+                        } else {
+                            Pattern::Or { alternatives }.t(s)
+                        }
+                    })
+            });
+
+            let is = cmp
+                .foldl(
+                    just(Token::Question)
+                        .map_with_span(Span)
+                        .then(pattern)
+                        .repeated(),
+                    |lhs, (question_token, pattern)| {
+                        let span = SimpleSpan::new(lhs.1.start, pattern.1.end);
+
+                        Expr::Is {
+                            lhs: Box::new(lhs),
+                            question_token,
+                            pattern: Box::new(pattern),
+                        }
+                        .t(span)
+                    },
+                )
+                .boxed();
+
+            is.map(Box::new)
         });
 
         let kwlet = kwlet!(expr);
@@ -395,27 +755,32 @@ pub fn parser<'t, 's: 't>() -> impl Parser<
             ]
         };
         let kwprint = group((
-            just(Token::Print).map_with_span(Span),
+            just(Token::Print)
+                .or(just(Token::Println))
+                .map_with_span(Span),
             just(Token::LeftParen).map_with_span(Span),
-            expr.clone(),
+            expr.clone()
+                .separated_by(just(Token::Comma))
+                .allow_trailing()
+                .collect::<Vec<_>>(),
             just(Token::RightParen).map_with_span(Span),
             just(Token::Semi).map_with_span(Span),
         ))
         .map_with_span(
-            |(print_token, left_paren, value, right_paren, semi_token), s| {
+            |(print_token, left_paren, values, right_paren, semi_token), s| {
                 Expr::Print {
                     left_paren,
                     print_token,
                     right_paren,
                     semi_token,
-                    value,
+                    values: values.into_iter().map(|x| *x).collect(),
                 }
                 .t(s)
             },
         );
         let kwreturn = chumsky::prelude::group((
             just(Token::Return).map_with_span(Span),
-            expr,
+            expr.clone(),
             just(Token::Semi).map_with_span(Span),
         ))
         .map_with_span(|(return_token, value, semi_token), s| {
@@ -427,8 +792,326 @@ pub fn parser<'t, 's: 't>() -> impl Parser<
             .t(s)
         });
 
-        choice![block, kwfn, kwreturn, kwprint, kwlet, assign]
+        let kwlock = group((
+            just(Token::Lock).map_with_span(Span),
+            expr.clone(),
+            just(Token::Semi).map_with_span(Span),
+        ))
+        .map_with_span(|(lock_token, target, semi_token), s| {
+            Expr::Lock {
+                lock_token,
+                target,
+                semi_token,
+            }
+            .t(s)
+        });
+
+        let kwuse = group((
+            just(Token::Use).map_with_span(Span),
+            ident
+                .separated_by(just(Token::DoubleColon))
+                .at_least(1)
+                .collect::<Vec<_>>(),
+            just(Token::Semi).map_with_span(Span),
+        ))
+        .map_with_span(|(use_token, path, semi_token), s| {
+            Expr::Use {
+                use_token,
+                path,
+                semi_token,
+            }
+            .t(s)
+        });
+
+        let letself = group((
+            just(Token::Let).map_with_span(Span),
+            just(Token::This).map_with_span(Span),
+            just(Token::DoubleColon).ignore_then(ident),
+            just(Token::Eq).map_with_span(Span),
+            expr.clone(),
+            just(Token::Semi).map_with_span(Span),
+        ))
+        .map_with_span(
+            |(let_token, self_token, ident, eq_token, expr, semi_token), s| {
+                Expr::LetSelf {
+                    let_token,
+                    self_token,
+                    ident,
+                    eq_token,
+                    expr,
+                    semi_token,
+                }
+                .t(s)
+            },
+        );
+
+        let kwif = recursive(|kwif| {
+            group((
+                just(Token::If).map_with_span(Span),
+                expr.clone(),
+                block.clone(),
+                just(Token::Else)
+                    .map_with_span(Span)
+                    .then(kwif.or(block.clone()))
+                    .or_not(),
+            ))
+            .map_with_span(|(if_token, condition, then, other), s| {
+                let (else_token, other) = other.unzip();
+
+                Expr::If {
+                    if_token,
+                    condition,
+                    then: Box::new(then),
+                    else_token,
+                    other: other.map(Box::new),
+                }
+                .t(s)
+            })
+        });
+
+        let kwwhile = group((
+            just(Token::While).map_with_span(Span),
+            expr.clone(),
+            block.clone(),
+        ))
+        .map_with_span(|(while_token, condition, then), s| {
+            Expr::While {
+                while_token,
+                condition,
+                then: Box::new(then),
+            }
+            .t(s)
+        });
+
+        let statement = expr
+            .clone()
+            .then(just(Token::Semi).map_with_span(Span))
+            .map_with_span(|(expr, semi_token), s| Expr::Statement { expr, semi_token }.t(s));
+
+        let index_assign = group((
+            expr.clone(),
+            one_of([
+                Token::Eq,
+                Token::PlusEq,
+                Token::MinusEq,
+                Token::StarEq,
+                Token::SlashEq,
+                Token::PercentEq,
+                Token::AndEq,
+                Token::PipeEq,
+                Token::CaretEq,
+                Token::ShlEq,
+                Token::ShrEq,
+            ])
+            .map_with_span(Span),
+            expr.clone(),
+            just(Token::Semi).map_with_span(Span),
+        ))
+        .try_map(|(target, eq_token, expr, semi_token), s| {
+            if matches!(target.0, Expr::Chain { .. }) {
+                Ok(Expr::IndexAssign {
+                    target,
+                    eq_token,
+                    expr,
+                    semi_token,
+                }
+                .t(s))
+            } else {
+                Err(Rich::custom(target.1, "expected an index expression"))
+            }
+        });
+
+        choice![
+            block,
+            kwif,
+            kwwhile,
+            kwunsafe,
+            kwfn,
+            method,
+            kwreturn,
+            kwprint,
+            letself,
+            kwlet,
+            kwlock,
+            kwuse,
+            assign,
+            index_assign,
+            statement
+        ]
     });
 
     stmt.repeated().at_least(1).collect()
 }
+
+/// The result of [`parse_at`]: the AST, if any, and the errors found on the way.
+pub type Parsed<'s> = (Option<Vec<Span<Expr>>>, Vec<Rich<'s, Token, RawSpan>>);
+
+/// Parses `tokens` as if `offset` other tokens came before them, so that the spans of the AST come
+/// out after those of every file parsed before it.
+#[must_use]
+pub fn parse_at<'s>(tokens: &[Token], offset: usize) -> Parsed<'s> {
+    // each token is spanned by its index, moved along by `offset`
+    let input = tokens
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (x.clone(), SimpleSpan::new(offset + i, offset + i + 1)))
+        .collect::<Vec<_>>();
+    let end = offset + tokens.len();
+
+    let (exprs, errors) = parser()
+        .parse(input.as_slice().spanned(SimpleSpan::new(end, end)))
+        .into_output_errors();
+
+    (exprs, errors.into_iter().map(Rich::into_owned).collect())
+}
+
+enum Postfix {
+    Call {
+        left_paren: Span<Token>,
+        arguments: Vec<Span<Expr>>,
+        right_paren: Span<Token>,
+    },
+    Index {
+        child: Box<Span<Expr>>,
+    },
+    Slice {
+        start: Option<Box<Span<Expr>>>,
+        end: Option<Box<Span<Expr>>>,
+        inclusive: bool,
+    },
+    Restrict {
+        left_brace: Span<Token>,
+        pred: Box<Span<Expr>>,
+        right_brace: Span<Token>,
+    },
+}
+
+fn binary(lhs: Span<Expr>, op: Span<Token>, rhs: Span<Expr>) -> Span<Expr> {
+    let span = SimpleSpan::new(lhs.1.start, rhs.1.end);
+    let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+
+    match op.0 {
+        Token::Star => Expr::Mul {
+            lhs,
+            star_token: op,
+            rhs,
+        },
+        Token::Slash => Expr::Div {
+            lhs,
+            slash_token: op,
+            rhs,
+        },
+        Token::Percent => Expr::Rem {
+            lhs,
+            percent_token: op,
+            rhs,
+        },
+        Token::Plus => Expr::Add {
+            lhs,
+            plus_token: op,
+            rhs,
+        },
+        Token::Minus => Expr::Sub {
+            lhs,
+            minus_token: op,
+            rhs,
+        },
+        Token::Shl => Expr::Shl {
+            lhs,
+            shl_token: op,
+            rhs,
+        },
+        Token::Shr => Expr::Shr {
+            lhs,
+            shr_token: op,
+            rhs,
+        },
+        Token::And => Expr::And {
+            lhs,
+            and_token: op,
+            rhs,
+        },
+        Token::Caret => Expr::Xor {
+            lhs,
+            caret_token: op,
+            rhs,
+        },
+        Token::Pipe => Expr::Or {
+            lhs,
+            pipe_token: op,
+            rhs,
+        },
+        Token::EqEq => Expr::Eq {
+            lhs,
+            eqeq_token: op,
+            rhs,
+        },
+        Token::Ne => Expr::Ne {
+            lhs,
+            ne_token: op,
+            rhs,
+        },
+        Token::Gt => Expr::Gt {
+            lhs,
+            gt_token: op,
+            rhs,
+        },
+        Token::Ge => Expr::Ge {
+            lhs,
+            ge_token: op,
+            rhs,
+        },
+        Token::Lt => Expr::Lt {
+            lhs,
+            lt_token: op,
+            rhs,
+        },
+        Token::Le => Expr::Le {
+            lhs,
+            le_token: op,
+            rhs,
+        },
+        _ => unreachable!("`{}` is not a binary operator", op.0),
+    }
+    .t(span)
+}
+
+fn approx(lhs: Span<Expr>, op: Span<Token>, epsilon: Span<Expr>, rhs: Span<Expr>) -> Span<Expr> {
+    let span = SimpleSpan::new(lhs.1.start, rhs.1.end);
+    let (lhs, epsilon, rhs) = (Box::new(lhs), Box::new(epsilon), Box::new(rhs));
+
+    match op.0 {
+        Token::GtTilde => Expr::AlmostGt {
+            lhs,
+            gt_tilde_token: op,
+            epsilon,
+            rhs,
+        },
+        Token::GeTilde => Expr::AlmostGe {
+            lhs,
+            ge_tilde_token: op,
+            epsilon,
+            rhs,
+        },
+        Token::LtTilde => Expr::AlmostLt {
+            lhs,
+            lt_tilde_token: op,
+            epsilon,
+            rhs,
+        },
+        Token::LeTilde => Expr::AlmostLe {
+            lhs,
+            le_tilde_token: op,
+            epsilon,
+            rhs,
+        },
+        Token::EqEqTilde => Expr::AlmostEq {
+            lhs,
+            eqeq_tilde_token: op,
+            epsilon,
+            rhs,
+        },
+        _ => unreachable!("`{}` is not an epsilon operator", op.0),
+    }
+    .t(span)
+}
@@ -0,0 +1,233 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{
+    common::Tag,
+    env::Env,
+    interpreter::{compare, print},
+    literal::{Args, Arity, Literal, Native},
+    rc_cell,
+    span::{RawSpan, Span},
+    stdlib::{iter, reflect},
+};
+
+/// Registers the builtin functions into `env`. They are visible from every scope that descends from
+/// it, and can be shadowed like any other name.
+pub fn prelude(env: &Env<'_>) {
+    native(env, "cmp", Arity::between(2, 3), cmp);
+    native(env, "assert", Arity::between(1, 2), assert);
+    native(env, "assert_eq", Arity::exactly(2), assert_eq);
+
+    native(env, "input", Arity::between(0, 1), input);
+    native(env, "read_line", Arity::exactly(0), read_line);
+    native(env, "read_all", Arity::exactly(0), read_all);
+    native(env, "eprint", Arity::at_least(0), |p, a, s| {
+        eprint(p, a, s, "")
+    });
+    native(env, "eprintln", Arity::at_least(0), |p, a, s| {
+        eprint(p, a, s, "\n")
+    });
+    native(env, "gc", Arity::exactly(0), |p, _, _| Ok(gc(p)));
+    native(env, "heap", Arity::exactly(0), |p, _, _| Ok(heap(p)));
+
+    native(env, "type_of", Arity::exactly(1), reflect::type_of);
+    native(env, "keys", Arity::exactly(1), reflect::keys);
+    native(env, "has", Arity::exactly(2), reflect::has);
+    native(env, "len", Arity::exactly(1), reflect::len);
+    native(env, "is_fn", Arity::exactly(1), reflect::is_fn);
+    native(env, "arity", Arity::exactly(1), reflect::arity);
+    native(env, "name", Arity::exactly(1), reflect::name);
+
+    native(env, "map", Arity::exactly(2), iter::map);
+    native(env, "filter", Arity::exactly(2), iter::filter);
+    native(env, "fold", Arity::exactly(3), iter::fold);
+    native(env, "reduce", Arity::exactly(2), iter::reduce);
+    native(env, "any", Arity::exactly(2), iter::any);
+    native(env, "all", Arity::exactly(2), iter::all);
+    native(env, "find", Arity::exactly(2), iter::find);
+    native(env, "sort", Arity::exactly(1), iter::sort);
+    native(env, "sort_by", Arity::exactly(2), iter::sort_by);
+    native(env, "reverse", Arity::exactly(1), iter::reverse);
+    native(env, "zip", Arity::exactly(2), iter::zip);
+    native(env, "enumerate", Arity::exactly(1), iter::enumerate);
+    native(env, "sum", Arity::exactly(1), iter::sum);
+    native(env, "count", Arity::between(1, 2), iter::count);
+}
+
+/// Registers one native function.
+pub fn native<'a>(
+    env: &Env<'a>,
+    name: &'a str,
+    arity: Arity,
+    f: impl Fn(&mut Env<'a>, &Args<'a>, RawSpan) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> + 'a,
+) {
+    env.builtins.borrow_mut().insert(
+        name.to_owned(),
+        rc_cell!(Literal::NativeFn(name, arity, Native(Rc::new(f)))),
+    );
+}
+
+/// `cmp(a, b)` orders two values, `cmp(a, b, epsilon)` treats values within `epsilon` as equal.
+fn cmp<'a>(
+    program: &mut Env<'a>,
+    args: &Args<'a>,
+    span: RawSpan,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    match args {
+        [lhs, rhs] => {
+            compare(program, &lhs.0, &rhs.0, span).map(|x| rc_cell!(Literal::Ordering(x)))
+        }
+        [lhs, rhs, epsilon] => {
+            let (lhs, rhs, epsilon) = (
+                lhs.0.borrow().clone(),
+                rhs.0.borrow().clone(),
+                epsilon.0.borrow().clone(),
+            );
+
+            lhs.rcmp_within(epsilon, rhs)
+                .map(|x| rc_cell!(x))
+                .map_err(|x| x.t(span))
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// `assert(cond)` or `assert(cond, message)` fails unless `cond` is `true`.
+fn assert<'a>(
+    _: &mut Env<'a>,
+    args: &Args<'a>,
+    span: RawSpan,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    let cond = match &*args[0].0.borrow() {
+        Literal::Bool(b) => *b,
+        c => {
+            return Err(format!("expected `bool`, found `{}`", c.type_of()).t(args[0].1));
+        }
+    };
+
+    if cond {
+        return Ok(rc_cell!(Literal::None));
+    }
+
+    Err(args
+        .get(1)
+        .map_or_else(
+            || "assertion failed".to_string(),
+            |message| format!("assertion failed: {}", message.0.borrow()),
+        )
+        .t(span))
+}
+
+/// `assert_eq(a, b)` fails unless `a == b`. Values that cannot be compared are not equal.
+fn assert_eq<'a>(
+    _: &mut Env<'a>,
+    args: &Args<'a>,
+    span: RawSpan,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    let (lhs, rhs) = (args[0].0.borrow().clone(), args[1].0.borrow().clone());
+
+    if matches!(lhs.clone().req(rhs.clone()), Ok(Literal::Bool(true))) {
+        return Ok(rc_cell!(Literal::None));
+    }
+
+    Err(format!("assertion failed: `{lhs} == {rhs}`").t(span))
+}
+
+fn read_error(e: &std::io::Error, span: RawSpan) -> Span<String> {
+    format!("cannot read input: {e}").t(span)
+}
+
+/// `input()` reads a line, `input(prompt)` prints `prompt` first. Fails at the end of the input.
+fn input<'a>(
+    program: &mut Env<'a>,
+    args: &Args<'a>,
+    span: RawSpan,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    if let Some(prompt) = args.first() {
+        let prompt = prompt.0.borrow().to_string();
+        print(program, &prompt, span)?;
+
+        let flushed = program.stdout.borrow_mut().flush();
+        flushed.map_err(|e| format!("cannot write output: {e}").t(span))?;
+    }
+
+    let line = program.input.borrow_mut().read_line();
+
+    let line = line
+        .map_err(|e| read_error(&e, span))?
+        .ok_or_else(|| "unexpected end of input".to_string().t(span))?;
+
+    Ok(rc_cell!(Literal::String(line)))
+}
+
+/// `read_line()` reads a line, or gives `_` at the end of the input.
+fn read_line<'a>(
+    program: &mut Env<'a>,
+    _: &Args<'a>,
+    span: RawSpan,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    let line = program.input.borrow_mut().read_line();
+
+    Ok(line
+        .map_err(|e| read_error(&e, span))?
+        .map_or_else(|| rc_cell!(Literal::None), |x| rc_cell!(Literal::String(x))))
+}
+
+/// `read_all()` reads everything that is left.
+fn read_all<'a>(
+    program: &mut Env<'a>,
+    _: &Args<'a>,
+    span: RawSpan,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    let all = program.input.borrow_mut().read_all();
+
+    Ok(rc_cell!(Literal::String(
+        all.map_err(|e| read_error(&e, span))?
+    )))
+}
+
+/// `eprint(...)` and `eprintln(...)` write their arguments to stderr, like `print` does to stdout.
+fn eprint<'a>(
+    program: &Env<'a>,
+    args: &Args<'a>,
+    span: RawSpan,
+    end: &str,
+) -> Result<Rc<RefCell<Literal<'a>>>, Span<String>> {
+    let text = args
+        .iter()
+        .map(|x| x.0.borrow().to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    program
+        .eprint(&(text + end))
+        .map_err(|e| format!("cannot write output: {e}").t(span))?;
+
+    Ok(rc_cell!(Literal::None))
+}
+
+fn int<'a>(n: impl TryInto<i64>) -> Rc<RefCell<Literal<'a>>> {
+    rc_cell!(Literal::Integer(n.try_into().unwrap_or(i64::MAX)))
+}
+
+/// `gc()` frees the values that only refer to each other, and gives how many there were.
+fn gc<'a>(program: &Env<'a>) -> Rc<RefCell<Literal<'a>>> {
+    int(program.heap.collect())
+}
+
+/// `heap()` is an object of how many values are `live`, how many `collections` the collector has
+/// made, and how many values it has `freed`.
+fn heap<'a>(program: &Env<'a>) -> Rc<RefCell<Literal<'a>>> {
+    let stats = program.heap.stats();
+
+    let fields = HashMap::from([
+        ("live".into(), int(stats.live)),
+        ("collections".into(), int(stats.collections)),
+        ("freed".into(), int(stats.freed)),
+    ]);
+
+    rc_cell!(Literal::Object(fields, HashSet::new()))
+}
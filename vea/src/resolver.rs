@@ -0,0 +1,499 @@
+//! Finds where every variable of a program lives before it runs.
+//!
+//! Both backends then declare and look up a variable by its slot instead of by its name. A variable that does not
+//! exist, or a `let` that would shadow another one, is an error before anything runs.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{Expr, Pattern},
+    common::{grow, Tag},
+    compiler::is_plain,
+    env::Env,
+    limits::MAX_NESTING,
+    literal::Literal,
+    span::{RawSpan, Span},
+    symbol::Symbol,
+};
+
+/// How many scopes up a variable is from where it is used, and its slot in that scope.
+pub type Slot = (usize, usize);
+
+/// The slots of resolved variables, by the token index of where they are used or declared.
+#[derive(Debug, Clone, Default)]
+pub struct Resolution(HashMap<usize, Slot>);
+
+impl Resolution {
+    #[must_use]
+    pub fn get(&self, ident: RawSpan) -> Option<Slot> {
+        self.0.get(&ident.start).copied()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// A block, which runs as soon as it is reached.
+    Block,
+    /// The body of a fn, which runs whenever the fn is called.
+    Fn,
+    /// The fields of `self` that a method sees, which are the variables of the scope at this index.
+    This(usize),
+}
+
+struct Level {
+    kind: Kind,
+    names: Vec<String>,
+    /// Variables used by fns declared in this scope, which may still be declared after them, and
+    /// how far up this scope is from where they are used.
+    pending: Vec<(Span<Symbol>, usize)>,
+}
+
+impl Level {
+    const fn new(kind: Kind, names: Vec<String>) -> Self {
+        Self {
+            kind,
+            names,
+            pending: vec![],
+        }
+    }
+}
+
+struct Resolver<'r> {
+    levels: Vec<Level>,
+    /// The scope that `let self::x = ...;` declares into.
+    this: Option<usize>,
+    builtins: HashSet<String>,
+    resolution: &'r mut Resolution,
+    /// How many expressions the one being resolved is inside of.
+    depth: usize,
+}
+
+/// Resolves a list of statements to be run in `env`, adding what it finds to `env.resolution`.
+pub fn resolve(stmts: &[Span<Expr>], env: &Env<'_>) -> Result<(), Span<String>> {
+    let mut levels = vec![];
+    let mut scope = Some(env.values.clone());
+    let mut parent = env.parent.clone();
+
+    while let Some(frame) = scope {
+        levels.push(Level::new(
+            Kind::Block,
+            frame
+                .borrow()
+                .layout()
+                .map(|x| x.cloned().unwrap_or_default())
+                .collect(),
+        ));
+        scope = parent.as_ref().map(|x| x.borrow().values.clone());
+        parent = parent.and_then(|x| x.borrow().parent.clone());
+    }
+
+    levels.reverse();
+
+    let mut resolution = env.resolution.borrow_mut();
+    let mut resolver = Resolver {
+        levels,
+        this: None,
+        builtins: env.builtins.borrow().keys().cloned().collect(),
+        resolution: &mut resolution,
+        depth: 0,
+    };
+
+    resolver.stmts(stmts)?;
+
+    while !resolver.levels.is_empty() {
+        resolver.pop()?;
+    }
+
+    Ok(())
+}
+
+fn undefined(ident: &Span<Symbol>) -> Span<String> {
+    format!("variable `{}` does not exist", ident.0).t(ident.1)
+}
+
+impl Resolver<'_> {
+    /// The slot of `name` in the scope at `i`.
+    fn find(&self, i: usize, name: &str) -> Option<usize> {
+        let names = match self.levels[i].kind {
+            Kind::This(j) => &self.levels[j].names,
+            _ => &self.levels[i].names,
+        };
+
+        names.iter().position(|x| x == name)
+    }
+
+    fn push(&mut self, kind: Kind, names: Vec<String>) {
+        self.levels.push(Level::new(kind, names));
+    }
+
+    /// Leaves a scope, resolving what fns declared in it were waiting for.
+    fn pop(&mut self) -> Result<(), Span<String>> {
+        let i = self.levels.len() - 1;
+        let pending = std::mem::take(&mut self.levels[i].pending);
+
+        for (ident, depth) in pending {
+            if let Some(slot) = self.find(i, &ident.0) {
+                self.resolution.0.insert(ident.1.start, (depth, slot));
+            } else if i > 0 {
+                self.levels[i - 1].pending.push((ident, depth + 1));
+            } else if !self.builtins.contains(&*ident.0) {
+                return Err(undefined(&ident));
+            }
+        }
+
+        self.levels.pop();
+        Ok(())
+    }
+
+    fn lookup(&mut self, ident: &Span<Symbol>) -> Result<(), Span<String>> {
+        for (depth, i) in (0..self.levels.len()).rev().enumerate() {
+            if let Some(slot) = self.find(i, &ident.0) {
+                self.resolution.0.insert(ident.1.start, (depth, slot));
+                return Ok(());
+            }
+
+            // a fn runs later, when anything around it may have been declared
+            if self.levels[i].kind != Kind::Block && i > 0 {
+                self.levels[i - 1].pending.push((ident.clone(), depth + 1));
+                return Ok(());
+            }
+        }
+
+        if self.builtins.contains(&*ident.0) {
+            Ok(())
+        } else {
+            Err(undefined(ident))
+        }
+    }
+
+    /// Fails if declaring `ident` here would clash with a variable that is already declared.
+    fn check_new(&self, ident: &Span<Symbol>) -> Result<(), Span<String>> {
+        let last = self.levels.len() - 1;
+
+        if let Some(i) = (0..=last).rev().find(|&i| self.find(i, &ident.0).is_some()) {
+            return Err(if i == last {
+                format!("variable `{}` already exists", ident.0)
+            } else {
+                format!(
+                    "variable `{}` shadows an outer variable with the same name",
+                    ident.0
+                )
+            }
+            .t(ident.1));
+        }
+
+        Ok(())
+    }
+
+    fn declare(&mut self, ident: &Span<Symbol>) -> Result<(), Span<String>> {
+        self.check_new(ident)?;
+
+        let names = &mut self.levels.last_mut().unwrap().names;
+        self.resolution.0.insert(ident.1.start, (0, names.len()));
+        names.push(ident.0.to_string());
+
+        Ok(())
+    }
+
+    /// Declares a field or method of `self`, which is also a variable of the fn it belongs to.
+    fn declare_this(&mut self, ident: &Span<Symbol>) {
+        if let Some(i) = self.this {
+            if self.find(i, &ident.0).is_none() {
+                let depth = self.levels.len() - 1 - i;
+                let names = &mut self.levels[i].names;
+
+                self.resolution
+                    .0
+                    .insert(ident.1.start, (depth, names.len()));
+                names.push(ident.0.to_string());
+            }
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Span<Expr>]) -> Result<(), Span<String>> {
+        for stmt in stmts {
+            self.expr(stmt)?;
+        }
+
+        Ok(())
+    }
+
+    /// A block, which only gets its own scope where the backends give it one.
+    fn block(&mut self, exprs: &[Span<Expr>], is_unsafe: bool) -> Result<(), Span<String>> {
+        if !is_unsafe && exprs.iter().all(|x| is_plain(&x.0)) {
+            return self.stmts(exprs);
+        }
+
+        self.push(Kind::Block, vec![]);
+        self.stmts(exprs)?;
+        self.pop()
+    }
+
+    fn function(
+        &mut self,
+        arguments: &[Span<Symbol>],
+        body: &Span<Expr>,
+        is_method: bool,
+    ) -> Result<(), Span<String>> {
+        let this = self.this;
+
+        if is_method {
+            let Some(i) = this else {
+                return Ok(());
+            };
+
+            self.push(Kind::This(i), vec![]);
+        }
+
+        let names = arguments.iter().map(|x| x.0.to_string()).collect();
+        self.push(Kind::Fn, names);
+
+        if !is_method {
+            self.this = Some(self.levels.len() - 1);
+        }
+
+        let result = match &body.0 {
+            Expr::Block { exprs, .. } => self.stmts(exprs),
+            _ => Ok(()),
+        };
+
+        self.this = this;
+        result?;
+        self.pop()?;
+
+        if is_method {
+            self.pop()?;
+        }
+
+        Ok(())
+    }
+
+    fn exprs(&mut self, exprs: &[Span<Expr>]) -> Result<(), Span<String>> {
+        exprs.iter().try_for_each(|x| self.expr(x))
+    }
+
+    fn expr(&mut self, expr: &Span<Expr>) -> Result<(), Span<String>> {
+        if self.depth == MAX_NESTING {
+            return Err(
+                format!("expression is nested more than {MAX_NESTING} levels deep").t(expr.1),
+            );
+        }
+
+        self.depth += 1;
+        let result = grow(|| self.visit(expr));
+        self.depth -= 1;
+
+        result
+    }
+
+    fn visit(&mut self, Span(expr, _): &Span<Expr>) -> Result<(), Span<String>> {
+        match expr {
+            Expr::Access { ident } => self.lookup(ident),
+
+            Expr::Let { ident, expr, .. } => {
+                self.check_new(ident)?;
+
+                if let Expr::Restrict { expr, pred, .. } = &expr.0 {
+                    self.expr(expr)?;
+                    self.push(Kind::Block, vec!["it".to_owned()]);
+                    self.expr(pred)?;
+                    self.pop()?;
+                } else {
+                    self.expr(expr)?;
+                }
+
+                self.declare(ident)
+            }
+
+            Expr::FnDecl {
+                name,
+                arguments,
+                block,
+                ..
+            } => {
+                self.declare(name)?;
+                self.function(arguments, block, false)
+            }
+
+            Expr::Method {
+                name,
+                arguments,
+                block,
+                ..
+            } => {
+                self.declare_this(name);
+                self.function(arguments, block, true)
+            }
+
+            Expr::LetSelf { ident, expr, .. } => {
+                self.expr(expr)?;
+                self.declare_this(ident);
+                Ok(())
+            }
+
+            Expr::Use { path, .. } => path.last().map_or(Ok(()), |x| self.declare(x)),
+
+            Expr::Assign { ident, expr, .. }
+            | Expr::AddAssign { ident, expr, .. }
+            | Expr::SubAssign { ident, expr, .. }
+            | Expr::MulAssign { ident, expr, .. }
+            | Expr::DivAssign { ident, expr, .. }
+            | Expr::RemAssign { ident, expr, .. }
+            | Expr::ShlAssign { ident, expr, .. }
+            | Expr::ShrAssign { ident, expr, .. }
+            | Expr::AndAssign { ident, expr, .. }
+            | Expr::OrAssign { ident, expr, .. }
+            | Expr::XorAssign { ident, expr, .. } => {
+                self.lookup(ident)?;
+                self.expr(expr)
+            }
+
+            Expr::IndexAssign { target, expr, .. } => {
+                self.expr(target)?;
+                self.expr(expr)
+            }
+
+            Expr::Block { exprs, .. } => self.block(exprs, false),
+            Expr::Unsafe { block, .. } => match &block.0 {
+                Expr::Block { exprs, .. } => self.block(exprs, true),
+                _ => Ok(()),
+            },
+
+            Expr::If {
+                condition,
+                then,
+                other,
+                ..
+            } => {
+                self.expr(condition)?;
+                self.expr(then)?;
+                other.as_ref().map_or(Ok(()), |x| self.expr(x))
+            }
+
+            Expr::While {
+                condition, then, ..
+            } => {
+                self.expr(condition)?;
+                self.expr(then)
+            }
+
+            Expr::Object { exprs, .. } => {
+                for Span(expr, _) in exprs {
+                    match expr {
+                        Expr::Let { expr, .. } => self.expr(expr)?,
+                        Expr::FnDecl {
+                            arguments, block, ..
+                        } => self.function(arguments, block, false)?,
+                        _ => {}
+                    }
+                }
+
+                Ok(())
+            }
+
+            Expr::Print { values: exprs, .. }
+            | Expr::Array { exprs, .. }
+            | Expr::Set { exprs, .. } => self.exprs(exprs),
+
+            Expr::Interp { holes, .. } => holes.iter().try_for_each(|x| self.expr(&x.0)),
+
+            Expr::FnCall {
+                access, arguments, ..
+            } => {
+                self.expr(access)?;
+                self.exprs(arguments)
+            }
+
+            Expr::Slice {
+                parent, start, end, ..
+            } => {
+                self.expr(parent)?;
+                start.as_ref().map_or(Ok(()), |x| self.expr(x))?;
+                end.as_ref().map_or(Ok(()), |x| self.expr(x))
+            }
+
+            Expr::Restrict { expr, pred, .. } => {
+                self.expr(expr)?;
+                self.push(Kind::Block, vec!["it".to_owned()]);
+                self.expr(pred)?;
+                self.pop()
+            }
+
+            Expr::Is { lhs, pattern, .. } => {
+                self.expr(lhs)?;
+                self.pattern(pattern)
+            }
+
+            Expr::Lock { target: expr, .. }
+            | Expr::Return { value: expr, .. }
+            | Expr::Statement { expr, .. }
+            | Expr::Group { expr, .. }
+            | Expr::Not { expr, .. }
+            | Expr::Neg { expr, .. }
+            | Expr::Copy { expr, .. } => self.expr(expr),
+
+            Expr::Chain {
+                parent: lhs,
+                child: rhs,
+            }
+            | Expr::Add { lhs, rhs, .. }
+            | Expr::Sub { lhs, rhs, .. }
+            | Expr::Mul { lhs, rhs, .. }
+            | Expr::Div { lhs, rhs, .. }
+            | Expr::Rem { lhs, rhs, .. }
+            | Expr::Shl { lhs, rhs, .. }
+            | Expr::Shr { lhs, rhs, .. }
+            | Expr::And { lhs, rhs, .. }
+            | Expr::Or { lhs, rhs, .. }
+            | Expr::Xor { lhs, rhs, .. }
+            | Expr::Eq { lhs, rhs, .. }
+            | Expr::Ne { lhs, rhs, .. }
+            | Expr::Gt { lhs, rhs, .. }
+            | Expr::Ge { lhs, rhs, .. }
+            | Expr::Lt { lhs, rhs, .. }
+            | Expr::Le { lhs, rhs, .. } => {
+                self.expr(lhs)?;
+                self.expr(rhs)
+            }
+
+            Expr::AlmostGt {
+                lhs, epsilon, rhs, ..
+            }
+            | Expr::AlmostGe {
+                lhs, epsilon, rhs, ..
+            }
+            | Expr::AlmostLt {
+                lhs, epsilon, rhs, ..
+            }
+            | Expr::AlmostLe {
+                lhs, epsilon, rhs, ..
+            }
+            | Expr::AlmostEq {
+                lhs, epsilon, rhs, ..
+            } => {
+                self.expr(lhs)?;
+                self.expr(epsilon)?;
+                self.expr(rhs)
+            }
+
+            Expr::Literal { .. }
+            | Expr::This { .. }
+            | Expr::Empty { .. }
+            | Expr::Error(_)
+            | Expr::None => Ok(()),
+        }
+    }
+
+    fn pattern(&mut self, Span(pattern, _): &Span<Pattern>) -> Result<(), Span<String>> {
+        match pattern {
+            Pattern::Ident { ident } if !Literal::TYPES.contains(&&*ident.0) => self.lookup(ident),
+            Pattern::Value { expr } => self.expr(expr),
+            Pattern::Object { fields } => fields.iter().try_for_each(|x| self.pattern(&x.1)),
+            Pattern::Set { items } | Pattern::Array { items } => {
+                items.iter().try_for_each(|x| self.pattern(x))
+            }
+            Pattern::Or { alternatives } => alternatives.iter().try_for_each(|x| self.pattern(x)),
+            _ => Ok(()),
+        }
+    }
+}
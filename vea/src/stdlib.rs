@@ -0,0 +1,91 @@
+//! Modules under `std::` that are written in Rust, rather than in Vea like `std::option`.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{
+    common::Tag,
+    env::Env,
+    literal::{Args, Arity, Literal, Native},
+    rc_cell,
+    span::{RawSpan, Span},
+    symbol::Symbol,
+};
+
+pub mod iter;
+pub mod math;
+pub mod reflect;
+pub mod string;
+
+/// What every function in a native module returns.
+pub type NativeResult<'a> = Result<Rc<RefCell<Literal<'a>>>, Span<String>>;
+
+/// The native module `std::name`, as an object of its functions.
+#[must_use]
+pub fn module<'a>(name: &str) -> Option<Literal<'a>> {
+    match name {
+        "math" => Some(math::module()),
+        "string" => Some(string::module()),
+        _ => None,
+    }
+}
+
+/// Builds a module object out of `(name, arity, function)`s.
+#[allow(clippy::type_complexity)]
+fn object<'a>(
+    fns: &[(
+        &'a str,
+        Arity,
+        fn(&mut Env<'a>, &Args<'a>, RawSpan) -> NativeResult<'a>,
+    )],
+) -> Literal<'a> {
+    let fields = fns
+        .iter()
+        .map(|&(name, arity, f)| {
+            (
+                Symbol::from(name),
+                rc_cell!(Literal::NativeFn(name, arity, Native(Rc::new(f)))),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    Literal::Object(fields, HashSet::new())
+}
+
+/// The error for an argument of the wrong type, pointing at that argument.
+fn expected<T>(ty: &str, arg: &Span<Rc<RefCell<Literal<'_>>>>) -> Result<T, Span<String>> {
+    Err(format!("expected `{ty}`, found `{}`", arg.0.borrow().type_of()).t(arg.1))
+}
+
+/// A length or index as an `int`, blaming `span` if it is too large.
+pub(crate) fn int<'a>(n: usize, span: RawSpan) -> NativeResult<'a> {
+    i64::try_from(n)
+        .map(|x| rc_cell!(Literal::Integer(x)))
+        .map_err(|_| "length does not fit in an `int`".to_string().t(span))
+}
+
+pub fn string_arg(arg: &Span<Rc<RefCell<Literal<'_>>>>) -> Result<String, Span<String>> {
+    match &*arg.0.borrow() {
+        Literal::String(s) => Ok(s.clone()),
+        _ => expected("str", arg),
+    }
+}
+
+pub fn int_arg(arg: &Span<Rc<RefCell<Literal<'_>>>>) -> Result<i64, Span<String>> {
+    match &*arg.0.borrow() {
+        Literal::Integer(i) => Ok(*i),
+        _ => expected("int", arg),
+    }
+}
+
+pub fn array_arg<'a>(
+    arg: &Span<Rc<RefCell<Literal<'a>>>>,
+) -> Result<Vec<Rc<RefCell<Literal<'a>>>>, Span<String>> {
+    match &*arg.0.borrow() {
+        Literal::Array(values, _) => Ok(values.clone()),
+        _ => expected("array", arg),
+    }
+}
@@ -0,0 +1,317 @@
+//! Functions over arrays and sets, registered as builtins by the prelude.
+//!
+//! Callbacks are called the same way `f(x)` would call them. If one fails, the error keeps its own
+//! span, and the callback that raised it is added to the trace.
+
+use std::{cell::RefCell, cmp::Ordering, rc::Rc};
+
+use crate::{
+    common::Tag,
+    env::Env,
+    interpreter::{call, compare},
+    literal::{Args, Literal, TotalOrdering},
+    rc_cell,
+    span::{RawSpan, Span},
+};
+
+use super::{expected, int, NativeResult};
+
+type Value<'a> = Rc<RefCell<Literal<'a>>>;
+
+/// The items of an array or set, and whether it was a set.
+fn items<'a>(arg: &Span<Value<'a>>) -> Result<(Vec<Value<'a>>, bool), Span<String>> {
+    match &*arg.0.borrow() {
+        Literal::Array(values, _) => Ok((values.clone(), false)),
+        Literal::Set(values) => Ok((values.clone(), true)),
+        _ => expected("array", arg),
+    }
+}
+
+/// Calls the callback `f` given to `name` with `args`.
+fn callback<'a>(
+    program: &mut Env<'a>,
+    name: &str,
+    f: &Span<Value<'a>>,
+    args: Vec<Value<'a>>,
+) -> NativeResult<'a> {
+    let value = f.0.borrow().clone();
+
+    call(program, &value, args, f.1).inspect_err(|_| {
+        program
+            .trace
+            .borrow_mut()
+            .push(format!("in the callback given to `{name}`").t(f.1));
+    })
+}
+
+/// Calls a callback that must return a `bool`.
+fn predicate<'a>(
+    program: &mut Env<'a>,
+    name: &str,
+    f: &Span<Value<'a>>,
+    value: Value<'a>,
+) -> Result<bool, Span<String>> {
+    match &*callback(program, name, f, vec![value])?.borrow() {
+        Literal::Bool(b) => Ok(*b),
+        c => Err(format!(
+            "the callback given to `{name}` must return `bool`, not `{}`",
+            c.type_of()
+        )
+        .t(f.1)),
+    }
+}
+
+fn array(values: Vec<Value<'_>>) -> Value<'_> {
+    rc_cell!(Literal::Array(values, false))
+}
+
+/// `map(xs, f)` is an array of `f(x)` for every `x`.
+pub fn map<'a>(program: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (values, _) = items(&args[0])?;
+    let mut out = vec![];
+
+    for value in values {
+        out.push(callback(program, "map", &args[1], vec![value])?);
+    }
+
+    Ok(array(out))
+}
+
+/// `filter(xs, f)` keeps every `x` where `f(x)` is `true`. Filtering a set gives a set.
+pub fn filter<'a>(program: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (values, is_set) = items(&args[0])?;
+    let mut out = vec![];
+
+    for value in values {
+        if predicate(program, "filter", &args[1], value.clone())? {
+            out.push(value);
+        }
+    }
+
+    Ok(rc_cell!(if is_set {
+        Literal::Set(out)
+    } else {
+        Literal::Array(out, false)
+    }))
+}
+
+/// `fold(xs, init, f)` starts from `init` and replaces it with `f(acc, x)` for every `x`.
+pub fn fold<'a>(program: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (values, _) = items(&args[0])?;
+    let mut acc = args[1].0.clone();
+
+    for value in values {
+        acc = callback(program, "fold", &args[2], vec![acc, value])?;
+    }
+
+    Ok(acc)
+}
+
+/// `reduce(xs, f)` is `fold` starting from the first item, or `_` if there are none.
+pub fn reduce<'a>(program: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (values, _) = items(&args[0])?;
+    let mut values = values.into_iter();
+
+    let Some(mut acc) = values.next() else {
+        return Ok(rc_cell!(Literal::None));
+    };
+
+    for value in values {
+        acc = callback(program, "reduce", &args[1], vec![acc, value])?;
+    }
+
+    Ok(acc)
+}
+
+pub fn any<'a>(program: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (values, _) = items(&args[0])?;
+
+    for value in values {
+        if predicate(program, "any", &args[1], value)? {
+            return Ok(rc_cell!(Literal::Bool(true)));
+        }
+    }
+
+    Ok(rc_cell!(Literal::Bool(false)))
+}
+
+pub fn all<'a>(program: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (values, _) = items(&args[0])?;
+
+    for value in values {
+        if !predicate(program, "all", &args[1], value)? {
+            return Ok(rc_cell!(Literal::Bool(false)));
+        }
+    }
+
+    Ok(rc_cell!(Literal::Bool(true)))
+}
+
+/// `find(xs, f)` is the first `x` where `f(x)` is `true`, or `_` if there is none.
+pub fn find<'a>(program: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (values, _) = items(&args[0])?;
+
+    for value in values {
+        if predicate(program, "find", &args[1], value.clone())? {
+            return Ok(value);
+        }
+    }
+
+    Ok(rc_cell!(Literal::None))
+}
+
+/// Sorts `values` with a comparison that can fail, stopping at the first error.
+///
+/// This is a stable merge sort, which gives some order whatever the comparison answers, where
+/// `sort_by` from std may panic if the answers contradict each other.
+fn sort_with<'a>(
+    values: &mut Vec<Value<'a>>,
+    mut cmp: impl FnMut(&Value<'a>, &Value<'a>) -> Result<Ordering, Span<String>>,
+) -> Result<(), Span<String>> {
+    let mut width = 1;
+
+    while width < values.len() {
+        let mut sorted = Vec::with_capacity(values.len());
+
+        for run in values.chunks(width * 2) {
+            let (mut lhs, mut rhs) = run.split_at(width.min(run.len()));
+
+            while let (Some(l), Some(r)) = (lhs.first(), rhs.first()) {
+                if cmp(l, r)? == Ordering::Greater {
+                    sorted.push(r.clone());
+                    rhs = &rhs[1..];
+                } else {
+                    sorted.push(l.clone());
+                    lhs = &lhs[1..];
+                }
+            }
+
+            sorted.extend_from_slice(lhs);
+            sorted.extend_from_slice(rhs);
+        }
+
+        *values = sorted;
+        width *= 2;
+    }
+
+    Ok(())
+}
+
+const fn ordering(o: TotalOrdering) -> Ordering {
+    match o {
+        TotalOrdering::LessThan
+        | TotalOrdering::LessThanOrEqual
+        | TotalOrdering::AlmostLessThan
+        | TotalOrdering::AlmostLessThanOrEqual => Ordering::Less,
+        TotalOrdering::GreaterThan
+        | TotalOrdering::GreaterThanOrEqual
+        | TotalOrdering::AlmostGreaterThan
+        | TotalOrdering::AlmostGreaterThanOrEqual => Ordering::Greater,
+        TotalOrdering::Equal | TotalOrdering::NotEqual | TotalOrdering::AlmostEqual => {
+            Ordering::Equal
+        }
+    }
+}
+
+/// `sort(xs)` is a sorted array of `xs`, in the same order as `cmp`.
+pub fn sort<'a>(program: &mut Env<'a>, args: &Args<'a>, span: RawSpan) -> NativeResult<'a> {
+    let (mut values, _) = items(&args[0])?;
+
+    sort_with(&mut values, |lhs, rhs| {
+        compare(program, lhs, rhs, span).map(ordering)
+    })?;
+
+    Ok(array(values))
+}
+
+/// `sort_by(xs, f)` sorts with `f(a, b)`, which returns an `ordering` like `cmp` does.
+pub fn sort_by<'a>(program: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (mut values, _) = items(&args[0])?;
+
+    sort_with(&mut values, |lhs, rhs| {
+        match &*callback(program, "sort_by", &args[1], vec![lhs.clone(), rhs.clone()])?.borrow() {
+            Literal::Ordering(o) => Ok(ordering(*o)),
+            c => Err(format!(
+                "the callback given to `sort_by` must return `ordering`, not `{}`",
+                c.type_of()
+            )
+            .t(args[1].1)),
+        }
+    })?;
+
+    Ok(array(values))
+}
+
+pub fn reverse<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (mut values, _) = items(&args[0])?;
+    values.reverse();
+
+    Ok(array(values))
+}
+
+/// `zip(xs, ys)` pairs up items as `[x, y]`, stopping at the end of the shorter one.
+pub fn zip<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let ((xs, _), (ys, _)) = (items(&args[0])?, items(&args[1])?);
+
+    Ok(array(
+        xs.into_iter()
+            .zip(ys)
+            .map(|(x, y)| array(vec![x, y]))
+            .collect(),
+    ))
+}
+
+/// `enumerate(xs)` pairs every item with its index as `[i, x]`.
+pub fn enumerate<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (values, _) = items(&args[0])?;
+    let mut out = vec![];
+
+    for (i, value) in values.into_iter().enumerate() {
+        out.push(array(vec![int(i, args[0].1)?, value]));
+    }
+
+    Ok(array(out))
+}
+
+pub fn sum<'a>(_: &mut Env<'a>, args: &Args<'a>, span: RawSpan) -> NativeResult<'a> {
+    let (values, _) = items(&args[0])?;
+    let mut sum = 0i64;
+
+    for (i, value) in values.iter().enumerate() {
+        let n = match &*value.borrow() {
+            Literal::Integer(n) => *n,
+            c => {
+                return Err(format!(
+                    "expected an array of `int`, found `{}` at index {i}",
+                    c.type_of()
+                )
+                .t(args[0].1))
+            }
+        };
+
+        sum = sum
+            .checked_add(n)
+            .ok_or_else(|| "result does not fit in an `int`".to_string().t(span))?;
+    }
+
+    Ok(rc_cell!(Literal::Integer(sum)))
+}
+
+/// `count(xs)` is the number of items, `count(xs, f)` the number where `f(x)` is `true`.
+pub fn count<'a>(program: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (values, _) = items(&args[0])?;
+
+    let Some(f) = args.get(1) else {
+        return int(values.len(), args[0].1);
+    };
+
+    let mut count = 0;
+
+    for value in values {
+        if predicate(program, "count", f, value)? {
+            count += 1;
+        }
+    }
+
+    int(count, args[0].1)
+}
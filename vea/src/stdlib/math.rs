@@ -0,0 +1,272 @@
+//! `use std::math;`
+//!
+//! Vea only has integers for now, so everything here works on `int`s and rounds down.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    common::Tag,
+    env::Env,
+    literal::{Args, Arity, Literal},
+    rc_cell,
+    span::{RawSpan, Span},
+};
+
+use super::{int_arg, object, NativeResult};
+
+#[must_use]
+pub fn module<'a>() -> Literal<'a> {
+    object(&[
+        ("abs", Arity::exactly(1), abs),
+        ("sign", Arity::exactly(1), sign),
+        ("min", Arity::at_least(1), min),
+        ("max", Arity::at_least(1), max),
+        ("pow", Arity::exactly(2), pow),
+        ("sqrt", Arity::exactly(1), sqrt),
+        ("isqrt", Arity::exactly(1), isqrt),
+        ("gcd", Arity::exactly(2), gcd),
+        ("lcm", Arity::exactly(2), lcm),
+        ("factorial", Arity::exactly(1), factorial),
+        ("binomial", Arity::exactly(2), binomial),
+        ("is_prime", Arity::exactly(1), is_prime),
+        ("mod_pow", Arity::exactly(3), mod_pow),
+        ("pi", Arity::exactly(1), pi),
+        ("e", Arity::exactly(1), e),
+    ])
+}
+
+fn int<'a>(x: i64) -> Rc<RefCell<Literal<'a>>> {
+    rc_cell!(Literal::Integer(x))
+}
+
+fn overflow(span: RawSpan) -> Span<String> {
+    "result does not fit in an `int`".to_string().t(span)
+}
+
+/// An argument that must not be negative.
+fn natural(arg: &Span<Rc<RefCell<Literal<'_>>>>) -> Result<i64, Span<String>> {
+    let n = int_arg(arg)?;
+
+    if n < 0 {
+        return Err(format!("expected a non-negative `int`, found {n}").t(arg.1));
+    }
+
+    Ok(n)
+}
+
+fn abs<'a>(_: &mut Env<'a>, args: &Args<'a>, span: RawSpan) -> NativeResult<'a> {
+    Ok(int(int_arg(&args[0])?
+        .checked_abs()
+        .ok_or_else(|| overflow(span))?))
+}
+
+fn sign<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    Ok(int(int_arg(&args[0])?.signum()))
+}
+
+fn min<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let mut min = i64::MAX;
+
+    for arg in args {
+        min = min.min(int_arg(arg)?);
+    }
+
+    Ok(int(min))
+}
+
+fn max<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let mut max = i64::MIN;
+
+    for arg in args {
+        max = max.max(int_arg(arg)?);
+    }
+
+    Ok(int(max))
+}
+
+fn pow<'a>(_: &mut Env<'a>, args: &Args<'a>, span: RawSpan) -> NativeResult<'a> {
+    let (base, exp) = (int_arg(&args[0])?, natural(&args[1])?);
+    let exp = u32::try_from(exp).map_err(|_| overflow(span))?;
+
+    Ok(int(base.checked_pow(exp).ok_or_else(|| overflow(span))?))
+}
+
+/// The square root of a perfect square.
+fn sqrt<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let n = natural(&args[0])?;
+    let root = n.isqrt();
+
+    if root * root != n {
+        return Err(format!("{n} is not a perfect square, use `isqrt` to round down").t(args[0].1));
+    }
+
+    Ok(int(root))
+}
+
+fn isqrt<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    Ok(int(natural(&args[0])?.isqrt()))
+}
+
+const fn euclid(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+
+    a
+}
+
+fn gcd<'a>(_: &mut Env<'a>, args: &Args<'a>, span: RawSpan) -> NativeResult<'a> {
+    let (a, b) = (int_arg(&args[0])?, int_arg(&args[1])?);
+    let gcd = euclid(a.unsigned_abs(), b.unsigned_abs());
+
+    Ok(int(i64::try_from(gcd).map_err(|_| overflow(span))?))
+}
+
+fn lcm<'a>(_: &mut Env<'a>, args: &Args<'a>, span: RawSpan) -> NativeResult<'a> {
+    let (a, b) = (int_arg(&args[0])?, int_arg(&args[1])?);
+
+    if a == 0 || b == 0 {
+        return Ok(int(0));
+    }
+
+    let (a, b) = (a.unsigned_abs(), b.unsigned_abs());
+    let lcm = (a / euclid(a, b))
+        .checked_mul(b)
+        .and_then(|x| i64::try_from(x).ok())
+        .ok_or_else(|| overflow(span))?;
+
+    Ok(int(lcm))
+}
+
+fn factorial<'a>(_: &mut Env<'a>, args: &Args<'a>, span: RawSpan) -> NativeResult<'a> {
+    let n = natural(&args[0])?;
+
+    Ok(int((1..=n)
+        .try_fold(1i64, i64::checked_mul)
+        .ok_or_else(|| overflow(span))?))
+}
+
+fn binomial<'a>(_: &mut Env<'a>, args: &Args<'a>, span: RawSpan) -> NativeResult<'a> {
+    let (n, k) = (natural(&args[0])?, natural(&args[1])?);
+
+    if k > n {
+        return Ok(int(0));
+    }
+
+    // every partial product is itself a binomial coefficient, so the division is exact
+    let k = k.min(n - k);
+    let mut result = 1i128;
+
+    for i in 0..k {
+        result = result * i128::from(n - i) / i128::from(i + 1);
+
+        if result > i128::from(i64::MAX) {
+            return Err(overflow(span));
+        }
+    }
+
+    Ok(int(i64::try_from(result).map_err(|_| overflow(span))?))
+}
+
+/// Whether `n` is prime, by Miller–Rabin with the first 12 primes as bases, which is exact for
+/// every `n` that fits in an `int`.
+fn is_prime<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+    let Ok(n) = u64::try_from(int_arg(&args[0])?) else {
+        return Ok(rc_cell!(Literal::Bool(false)));
+    };
+
+    if n < 2 {
+        return Ok(rc_cell!(Literal::Bool(false)));
+    }
+
+    if let Some(&p) = BASES.iter().find(|&&p| n % p == 0) {
+        return Ok(rc_cell!(Literal::Bool(n == p)));
+    }
+
+    // n - 1 = d * 2^s, with d odd
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    let (n, minus_one) = (u128::from(n), u128::from(n - 1));
+
+    let prime = BASES.iter().all(|&a| {
+        let mut x = pow_mod(u128::from(a), d, n);
+
+        if x == 1 || x == minus_one {
+            return true;
+        }
+
+        (1..s).any(|_| {
+            x = x * x % n;
+            x == minus_one
+        })
+    });
+
+    Ok(rc_cell!(Literal::Bool(prime)))
+}
+
+fn mod_pow<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (base, exp, modulus) = (int_arg(&args[0])?, natural(&args[1])?, int_arg(&args[2])?);
+
+    if modulus <= 0 {
+        return Err(format!("expected a positive modulus, found {modulus}").t(args[2].1));
+    }
+
+    let base = base.rem_euclid(modulus).unsigned_abs();
+    let result = pow_mod(
+        u128::from(base),
+        exp.unsigned_abs(),
+        u128::from(modulus.unsigned_abs()),
+    );
+
+    Ok(int(i64::try_from(result).expect("less than the modulus")))
+}
+
+/// `base^exp % modulus`, for a `modulus` that fits in an `int`, so that no product overflows.
+const fn pow_mod(base: u128, mut exp: u64, modulus: u128) -> u128 {
+    let mut base = base % modulus;
+    let mut result = 1 % modulus;
+
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = result * base % modulus;
+        }
+
+        base = base * base % modulus;
+        exp >>= 1;
+    }
+
+    result
+}
+
+/// The first digits of π and e, enough for any `int` that starts with them.
+const PI: &str = "3141592653589793238";
+const E: &str = "2718281828459045235";
+
+/// The constant with `digits`, rounded down after `n` decimal places, like `pi(2)` is 314.
+fn fixed<'a>(digits: &str, n: &Span<Rc<RefCell<Literal<'a>>>>) -> NativeResult<'a> {
+    let places = natural(n)?;
+    let len = usize::try_from(places + 1)
+        .ok()
+        .filter(|&len| len <= digits.len())
+        .ok_or_else(|| {
+            format!(
+                "expected at most {} decimal places, found {places}",
+                digits.len() - 1
+            )
+            .t(n.1)
+        })?;
+
+    Ok(int(digits[..len]
+        .parse()
+        .expect("a digit string that fits")))
+}
+
+fn pi<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    fixed(PI, &args[0])
+}
+
+fn e<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    fixed(E, &args[0])
+}
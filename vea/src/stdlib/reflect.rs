@@ -0,0 +1,101 @@
+//! Functions that look at values themselves, registered as builtins by the prelude.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    common::Tag,
+    env::Env,
+    literal::{Args, Literal},
+    rc_cell,
+    span::{RawSpan, Span},
+};
+
+use super::{expected, int, string_arg, NativeResult};
+
+/// The names of the fields of an object or env, sorted.
+fn field_names(arg: &Span<Rc<RefCell<Literal<'_>>>>) -> Result<Vec<String>, Span<String>> {
+    let mut keys = match &*arg.0.borrow() {
+        Literal::Object(fields, ..) => fields.keys().map(ToString::to_string).collect(),
+        Literal::Env(scope) => scope.borrow().keys().cloned().collect::<Vec<_>>(),
+        _ => return expected("object", arg),
+    };
+
+    keys.sort();
+    Ok(keys)
+}
+
+pub fn type_of<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    Ok(rc_cell!(Literal::String(args[0].0.borrow().type_of())))
+}
+
+/// `keys(obj)` is an array of the field names of an object or env.
+pub fn keys<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    Ok(rc_cell!(Literal::Array(
+        field_names(&args[0])?
+            .into_iter()
+            .map(|x| rc_cell!(Literal::String(x)))
+            .collect(),
+        false
+    )))
+}
+
+/// `has(obj, 'k')` is whether an object or env has a field `k`.
+pub fn has<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let key = string_arg(&args[1])?;
+    let has = field_names(&args[0])?.contains(&key);
+
+    Ok(rc_cell!(Literal::Bool(has)))
+}
+
+/// `len(x)` counts the characters of a string, the items of an array or set, or the fields of an
+/// object or env.
+pub fn len<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let len = match &*args[0].0.borrow() {
+        Literal::String(s) => s.chars().count(),
+        Literal::Array(values, _) | Literal::Set(values) => values.len(),
+        Literal::Object(fields, ..) => fields.len(),
+        Literal::Env(scope) => scope.borrow().len(),
+        c => {
+            return Err(format!("a value of type `{}` has no length", c.type_of()).t(args[0].1));
+        }
+    };
+
+    int(len, args[0].1)
+}
+
+pub fn is_fn<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let is_fn = matches!(
+        &*args[0].0.borrow(),
+        Literal::Fn(..) | Literal::NativeFn(..)
+    );
+    Ok(rc_cell!(Literal::Bool(is_fn)))
+}
+
+/// `arity(f)` is the number of arguments `f` takes. For a builtin that takes a range of them, it is
+/// `[min, max]` instead, where `max` is `_` if there is no limit.
+pub fn arity<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let span = args[0].1;
+
+    match &*args[0].0.borrow() {
+        Literal::Fn(_, argv, ..) => int(argv.len(), span),
+        Literal::NativeFn(_, arity, ..) if arity.max == Some(arity.min) => int(arity.min, span),
+        Literal::NativeFn(_, arity, ..) => Ok(rc_cell!(Literal::Array(
+            vec![
+                int(arity.min, span)?,
+                arity
+                    .max
+                    .map_or_else(|| Ok(rc_cell!(Literal::None)), |x| int(x, span))?,
+            ],
+            false
+        ))),
+        _ => expected("fn", &args[0]),
+    }
+}
+
+pub fn name<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    match &*args[0].0.borrow() {
+        Literal::Fn(name, ..) => Ok(rc_cell!(Literal::String(name.0.to_string()))),
+        Literal::NativeFn(name, ..) => Ok(rc_cell!(Literal::String((*name).to_owned()))),
+        _ => expected("fn", &args[0]),
+    }
+}
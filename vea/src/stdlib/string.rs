@@ -0,0 +1,180 @@
+//! `use std::string;`
+//!
+//! Lengths and indices count Unicode scalar values, the same as `s[i]` and `s[a..b]`, except for
+//! `byte_len`.
+
+use crate::{
+    common::Tag,
+    env::Env,
+    literal::{Args, Arity, Literal},
+    rc_cell,
+    span::RawSpan,
+};
+
+use super::{array_arg, int, int_arg, object, string_arg, NativeResult};
+
+#[must_use]
+pub fn module<'a>() -> Literal<'a> {
+    object(&[
+        ("len", Arity::exactly(1), len),
+        ("byte_len", Arity::exactly(1), byte_len),
+        ("chars", Arity::exactly(1), chars),
+        ("split", Arity::exactly(2), split),
+        ("join", Arity::exactly(2), join),
+        ("trim", Arity::exactly(1), trim),
+        ("upper", Arity::exactly(1), upper),
+        ("lower", Arity::exactly(1), lower),
+        ("replace", Arity::exactly(3), replace),
+        ("contains", Arity::exactly(2), contains),
+        ("starts_with", Arity::exactly(2), starts_with),
+        ("ends_with", Arity::exactly(2), ends_with),
+        ("find", Arity::exactly(2), find),
+        ("repeat", Arity::exactly(2), repeat),
+        ("parse_int", Arity::exactly(1), parse_int),
+    ])
+}
+
+fn len<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let s = string_arg(&args[0])?;
+    int(s.chars().count(), args[0].1)
+}
+
+fn byte_len<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let s = string_arg(&args[0])?;
+    int(s.len(), args[0].1)
+}
+
+fn chars<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let s = string_arg(&args[0])?;
+
+    Ok(rc_cell!(Literal::Array(
+        s.chars()
+            .map(|c| rc_cell!(Literal::String(c.to_string())))
+            .collect(),
+        false
+    )))
+}
+
+fn split<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (s, sep) = (string_arg(&args[0])?, string_arg(&args[1])?);
+
+    if sep.is_empty() {
+        return Err("cannot split on an empty string, use `chars` instead"
+            .to_string()
+            .t(args[1].1));
+    }
+
+    Ok(rc_cell!(Literal::Array(
+        s.split(&sep)
+            .map(|x| rc_cell!(Literal::String(x.to_owned())))
+            .collect(),
+        false
+    )))
+}
+
+fn join<'a>(program: &mut Env<'a>, args: &Args<'a>, span: RawSpan) -> NativeResult<'a> {
+    let (values, sep) = (array_arg(&args[0])?, string_arg(&args[1])?);
+    let mut parts = vec![];
+
+    for (i, value) in values.iter().enumerate() {
+        match &*value.borrow() {
+            Literal::String(s) => parts.push(s.clone()),
+            c => {
+                return Err(format!(
+                    "expected an array of `str`, found `{}` at index {i}",
+                    c.type_of()
+                )
+                .t(args[0].1))
+            }
+        }
+    }
+
+    let size = parts.iter().map(String::len).sum::<usize>()
+        + sep.len().saturating_mul(parts.len().saturating_sub(1));
+    program.meter.size("str", size, span)?;
+
+    Ok(rc_cell!(Literal::String(parts.join(&sep))))
+}
+
+fn trim<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let s = string_arg(&args[0])?;
+    Ok(rc_cell!(Literal::String(s.trim().to_owned())))
+}
+
+fn upper<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let s = string_arg(&args[0])?;
+    Ok(rc_cell!(Literal::String(s.to_uppercase())))
+}
+
+fn lower<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let s = string_arg(&args[0])?;
+    Ok(rc_cell!(Literal::String(s.to_lowercase())))
+}
+
+fn replace<'a>(program: &mut Env<'a>, args: &Args<'a>, span: RawSpan) -> NativeResult<'a> {
+    let (s, from, to) = (
+        string_arg(&args[0])?,
+        string_arg(&args[1])?,
+        string_arg(&args[2])?,
+    );
+
+    if from.is_empty() {
+        return Err("cannot replace an empty string".to_string().t(args[1].1));
+    }
+
+    let n = s.matches(&from).count();
+    let size = (s.len() - n * from.len()).saturating_add(n.saturating_mul(to.len()));
+    program.meter.size("str", size, span)?;
+
+    Ok(rc_cell!(Literal::String(s.replace(&from, &to))))
+}
+
+fn contains<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (s, pat) = (string_arg(&args[0])?, string_arg(&args[1])?);
+    Ok(rc_cell!(Literal::Bool(s.contains(&pat))))
+}
+
+fn starts_with<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (s, pat) = (string_arg(&args[0])?, string_arg(&args[1])?);
+    Ok(rc_cell!(Literal::Bool(s.starts_with(&pat))))
+}
+
+fn ends_with<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (s, pat) = (string_arg(&args[0])?, string_arg(&args[1])?);
+    Ok(rc_cell!(Literal::Bool(s.ends_with(&pat))))
+}
+
+/// The index of the first `pat` in `s`, or `_` if there is none. Test for it with `?int`.
+fn find<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let (s, pat) = (string_arg(&args[0])?, string_arg(&args[1])?);
+
+    s.find(&pat).map_or_else(
+        || Ok(rc_cell!(Literal::None)),
+        |i| int(s[..i].chars().count(), args[0].1),
+    )
+}
+
+fn repeat<'a>(program: &mut Env<'a>, args: &Args<'a>, span: RawSpan) -> NativeResult<'a> {
+    let (s, n) = (string_arg(&args[0])?, int_arg(&args[1])?);
+
+    let n =
+        usize::try_from(n).map_err(|_| format!("cannot repeat a string {n} times").t(args[1].1))?;
+
+    let Some(size) = s.len().checked_mul(n) else {
+        return Err(format!("cannot repeat a string {n} times").t(args[1].1));
+    };
+
+    // before it is made, as it may be too big for memory
+    program.meter.size("str", size, span)?;
+
+    Ok(rc_cell!(Literal::String(s.repeat(n))))
+}
+
+fn parse_int<'a>(_: &mut Env<'a>, args: &Args<'a>, _: RawSpan) -> NativeResult<'a> {
+    let s = string_arg(&args[0])?;
+
+    s.trim()
+        .parse::<i64>()
+        .map(|x| rc_cell!(Literal::Integer(x)))
+        .map_err(|_| format!("cannot parse '{s}' as an `int`").t(args[0].1))
+}
@@ -0,0 +1,8 @@
+//! Names that tokens, the AST and values own, so that they don't borrow the source they were read
+//! from.
+
+use std::sync::Arc;
+
+/// A name, freed once nothing refers to it anymore. It is shared rather than copied when cloned, and
+/// can be sent to another thread along with the program it is in.
+pub type Symbol = Arc<str>;
@@ -10,3 +10,1111 @@
 //     // dbg!(value);
 //     dbg!(super::interpreter::exec(exprs, &mut env)).unwrap();
 // }
+
+use std::{cell::RefCell, rc::Rc};
+
+fn run(src: &str) -> String {
+    let tokens = super::lex(src).0.unwrap();
+    let exprs = super::parse(src, &tokens).0.unwrap();
+
+    super::interp(src, &tokens, exprs)
+}
+
+fn run_vm(src: &str) -> String {
+    let tokens = super::lex(src).0.unwrap();
+    let exprs = super::parse(src, &tokens).0.unwrap();
+
+    let env = super::new_env();
+    env.borrow_mut().backend = super::vm::Backend::Vm;
+    super::interp_in(src, &tokens, exprs, &env)
+}
+
+fn test(src: &str) -> String {
+    let tokens = super::lex(src).0.unwrap();
+    let exprs = super::parse(src, &tokens).0.unwrap();
+
+    super::test(src, &tokens, &exprs)
+}
+
+fn option(src: &str) -> String {
+    run(&format!(
+        "use std::option;
+        let a = option::option()::some(5);
+        let b = option::option()::none();
+        {src}"
+    ))
+}
+
+#[test]
+fn option_some() {
+    assert_eq!(option("print(a::data == 5);"), "true");
+}
+
+#[test]
+fn option_none() {
+    assert_eq!(option("print(b::is_none());"), "true");
+}
+
+#[test]
+fn option_is_some() {
+    assert_eq!(
+        option("print(a::is_some()); print(b::is_some());"),
+        "truefalse"
+    );
+}
+
+#[test]
+fn option_then() {
+    let out = option(
+        "let seen = struct { let v = 0; };
+        fn see(x) { seen::v = x; return 0; }
+        b::then(see);
+        print(seen::v);
+        a::then(see);
+        print(seen::v);",
+    );
+
+    assert_eq!(out, "05");
+}
+
+#[test]
+fn option_unwrap_or_else() {
+    let out = option(
+        "fn seven() { return 7; }
+        print(a::unwrap_or_else(seven));
+        print(b::unwrap_or_else(seven));",
+    );
+
+    assert_eq!(out, "57");
+}
+
+#[test]
+fn option_unwrap_or() {
+    assert_eq!(
+        option("print(a::unwrap_or(3)); print(b::unwrap_or(3));"),
+        "53"
+    );
+}
+
+#[test]
+fn option_unwrap() {
+    let out = option(
+        "let out = struct { let v = 0; };
+        unsafe { out::v = a::unwrap(); }
+        print(out::v);",
+    );
+
+    assert_eq!(out, "5");
+    assert!(option("a::unwrap();").contains("can only be called inside an `unsafe` block"));
+}
+
+#[test]
+fn assert_eq_failure() {
+    assert!(run("assert_eq(1 + 1, 3);").contains("assertion failed: `2 == 3`"));
+    assert!(run("assert(1 == 2, 'math');").contains("assertion failed: math"));
+    assert_eq!(run("assert(true); assert_eq('a', 'a'); print(1);"), "1");
+}
+
+#[test]
+fn test_runner() {
+    let out = test(
+        "fn double(x) { return x * 2; }
+        test fn doubles() { assert_eq(double(2), 4); }
+        test fn fails() { assert_eq(double(2), 5); }
+        test fn runs_alone() { let x = 1; assert_eq(x, 1); }",
+    );
+
+    assert!(out.starts_with("running 3 tests\n"));
+    assert!(out.contains("test doubles ... ok"));
+    assert!(out.contains("test fails ... FAILED"));
+    assert!(out.contains("test runs_alone ... ok"));
+    assert!(out.contains("assertion failed: `4 == 5`"));
+    assert!(out.ends_with("test result: FAILED. 2 passed; 1 failed\n"));
+
+    // a test body runs like any other fn body, on either backend
+    let src = "test fn returns() { let x = 1; if x == 1 { return x; } assert(false, 'ran on'); }
+        test fn recurses() { fn f(n) { return f(n + 1); } f(0); }";
+    let tokens = super::lex(src).0.unwrap();
+    let exprs = super::parse(src, &tokens).0.unwrap();
+
+    for backend in [super::vm::Backend::Tree, super::vm::Backend::Vm] {
+        let new_env = || {
+            let env = super::new_env();
+            env.borrow_mut().backend = backend;
+            env.borrow_mut().meter = Rc::new(super::limits::Meter::new(super::limits::Limits {
+                steps: Some(10_000),
+                ..super::limits::Limits::default()
+            }));
+            env
+        };
+        let out = super::test_in(src, &tokens, &exprs, new_env);
+
+        assert!(out.contains("test returns ... ok"), "{out}");
+        assert!(out.contains("test recurses ... FAILED"), "{out}");
+        assert!(out.contains("ran out of steps after 10000"), "{out}");
+    }
+}
+
+#[test]
+fn higher_order() {
+    let out = run("fn double(x) { return x * 2; }
+        fn add(a, b) { return a + b; }
+        print(map([1, 2, 3], double));
+        print(fold([1, 2, 3], 10, add));
+        print(sort([3, 1, 2]));
+        print(zip([1, 2, 3], [4, 5]));");
+
+    assert_eq!(out, "[2, 4, 6]16[1, 2, 3][[1, 4], [2, 5]]");
+
+    let out = run("fn bad(x) { return x + true; } map([1], bad);");
+    assert!(out.contains("cannot add `int + bool`"));
+    assert!(out.contains("in the callback given to `map`"));
+
+    // a comparator that contradicts itself still gives some order
+    let xs = format!("{:?}", (0..64).map(|i| i * 7919 % 1000).collect::<Vec<_>>());
+
+    for run in [run, run_vm] {
+        let out = run(&format!(
+            "fn gt(a, b) {{ return cmp(a % 10, b % 7); }}
+            print(len(sort_by({xs}, gt)), sort_by([1, 2, 3], gt));"
+        ));
+        assert_eq!(out, "64 [1, 2, 3]", "{out}");
+    }
+}
+
+#[test]
+#[allow(clippy::literal_string_with_formatting_args)]
+fn interpolation() {
+    let out = run("let x = 5; let y = -3; println('x = {x}, y = {y + 1}'); print(1, 'a', [2]);");
+    assert_eq!(out, "x = 5, y = -2\n1 a [2]");
+
+    let out = run(
+        "let n = 5; let s = ['a']; print('{n:08b}|{255:x}|{n:05}|{n:>3}|{n:*^5}|{{n}}|{s:?}');",
+    );
+    assert_eq!(out, "00000101|ff|00005|  5|**5**|{n}|['a']");
+
+    // strings are quoted at any depth, so `'1'` and `1` do not look the same
+    let out = run(
+        "let o = struct { let a = '1'; let b = ['x', set { 'y' }]; }; let p = struct { let a = 1; };
+        print('{o:?} {p:?}');",
+    );
+    assert_eq!(
+        out,
+        "object { a: '1', b: ['x', set { 'y' }] } object { a: 1 }"
+    );
+    assert_eq!(
+        super::format::debug(&super::literal::Literal::String("it's\\\n".to_owned())),
+        r"'it\'s\\\n'"
+    );
+
+    for run in [run, run_vm] {
+        let out = run("print('{1:18446744073709551615}');");
+        assert!(
+            out.contains("width `18446744073709551615` is too large"),
+            "{out}"
+        );
+    }
+}
+
+#[test]
+fn input() {
+    let src = "let name = input('name? '); print('hi {name}|'); print(read_line(), read_all(), read_line());";
+    let tokens = super::lex(src).0.unwrap();
+    let exprs = super::parse(src, &tokens).0.unwrap();
+
+    let out = super::interp_with_input(
+        src,
+        &tokens,
+        exprs,
+        super::input::Text::new("vea\r\n2\n3\n4"),
+    );
+    assert_eq!(out, "name? hi vea|2 3\n4 _");
+
+    assert!(run("input();").contains("unexpected end of input"));
+}
+
+#[test]
+fn reflection() {
+    let out = run(
+        "let o = struct { let a = 1; let b = 'x'; fn f(y) { return y; } };
+        println(o);
+        print(type_of(o), keys(o), has(o, 'a'), len(o), is_fn(o::f), arity(o::f), arity(cmp), name(o::f));",
+    );
+
+    assert_eq!(
+        out,
+        "object { a: 1, b: x, f: fn f(1) }\nobject [a, b, f] true 3 true 1 [2, 3] f"
+    );
+}
+
+#[test]
+fn vm() {
+    let programs = [
+        "fn fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); }
+        let i = 0; let s = 0;
+        while i < 100 { s += i; i += 1; }
+        print(fib(5), s, [i][0], 'i = {i:x}', map([1, 2], fib));",
+        "fn f() { let k = 1; { let j = 2; return k + j; } } print(f());",
+        "let x = 1; if x { print(1); }",
+        "fn g(a) { return a; print(a); } g(1);",
+        "fn g(a, b) { return a; } g(1);",
+        "let y = 5; print(y[0]);",
+        "let a = 1; let a = 2;",
+        "return 1;",
+    ];
+
+    for src in programs {
+        assert_eq!(run_vm(src), run(src), "{src}");
+    }
+
+    assert_eq!(run_vm(programs[0]), "5 4950 100 i = 64 [1, 1]");
+}
+
+#[test]
+fn resolver() {
+    let out = run("print(1); print(nope);");
+    assert!(!out.starts_with('1'), "{out}");
+    assert!(out.contains("variable `nope` does not exist"), "{out}");
+
+    let out = run("let x = 1; { let x = 2; }");
+    assert!(out.contains("shadows an outer variable"), "{out}");
+
+    let src = "fn f() { return later + 1; } let later = 2; print(f());";
+    assert_eq!(run(src), "3");
+    assert_eq!(run_vm(src), "3");
+
+    for run in [run, run_vm] {
+        // a variable is only there once its `let` has run, even though its slot is known
+        let out = run("fn f() { return later; } print(f()); let later = 2;");
+        assert!(out.contains("variable `later` does not exist"), "{out}");
+
+        // a field of `self` in a branch that didn't run leaves its slot empty
+        let src = "fn f(on) { if on { let self::a = 1; } let b = 2; { let self::c = b + 1; } }
+            let x = f(false); let y = f(true);
+            print(x::b, x::c, y::a, y::b, y::c, keys(x));";
+        assert_eq!(run(src), "2 3 1 2 3 [b, c, on]");
+    }
+}
+
+#[test]
+fn lexical_scope() {
+    let src = "fn f() { return later * 2; }
+        let later = 21;
+        fn make() { let n = 3; fn get() { return n; } return get; }
+        let get = make();
+        print(f(), get());";
+    assert_eq!(run(src), "42 3");
+    assert_eq!(run_vm(src), "42 3");
+
+    let out = run("fn f() { return y; } fn g() { let y = 1; return f(); } print(g());");
+    assert!(out.contains("variable `y` does not exist"), "{out}");
+    assert!(out.contains("fn f() { return y; }"), "{out}");
+}
+
+#[test]
+fn output() {
+    let src = "fn f(x) { println('in f', x); return x; }
+        { print('block '); }
+        println(f(1));
+        eprintln('oops');
+        print('end');";
+    assert_eq!(run(src), "block in f 1\n1\noops\nend");
+    assert_eq!(run_vm(src), run(src));
+
+    let tokens = super::lex(src).0.unwrap();
+    let exprs = super::parse(src, &tokens).0.unwrap();
+    let lines = Rc::new(RefCell::new(vec![]));
+    let seen = lines.clone();
+
+    let env = super::new_env();
+    env.borrow_mut().stderr = Rc::new(RefCell::new(super::output::Lines::new(move |x| {
+        seen.borrow_mut().push(x.to_owned());
+    })));
+
+    assert_eq!(
+        super::interp_in(src, &tokens, exprs, &env),
+        "block in f 1\n1\nend"
+    );
+    assert_eq!(*lines.borrow(), ["oops"]);
+}
+
+#[test]
+fn limits() {
+    use super::{
+        limits::{Limits, Meter},
+        vm::Backend,
+    };
+
+    let run_with = |src: &str, limits| {
+        let tokens = super::lex(src).0.unwrap();
+        let exprs = super::parse(src, &tokens).0.unwrap();
+        super::interp_with_limits(src, &tokens, exprs, limits)
+    };
+
+    let steps = Limits {
+        steps: Some(1000),
+        ..Limits::default()
+    };
+    let out = run_with("let i = 0; while true { i += 1; }", steps);
+    assert!(out.contains("ran out of steps after 1000"), "{out}");
+
+    let depth = Limits {
+        depth: Some(3),
+        ..Limits::default()
+    };
+    let out = run_with("fn f(n) { return 1 + f(n + 1); } f(0);", depth);
+    assert!(
+        out.contains("exceeded the maximum call depth of 3"),
+        "{out}"
+    );
+
+    let size = Limits {
+        size: Some(8),
+        ..Limits::default()
+    };
+    let out = run_with("let s = 'ab'; while true { s = s + s; }", size);
+    assert!(
+        out.contains("with a size of 16 exceeds the maximum size of 8"),
+        "{out}"
+    );
+
+    // padding is checked before it is made
+    for src in ["print('{1:4000000000}');", "print('{1:04000000000}');"] {
+        let out = run_with(src, size);
+        assert!(
+            out.contains("with a size of 4000000000 exceeds the maximum size of 8"),
+            "{out}"
+        );
+    }
+
+    let allocations = Limits {
+        allocations: Some(50),
+        ..Limits::default()
+    };
+    let out = run_with("let a = []; while true { a = [a]; }", allocations);
+    assert!(
+        out.contains("exceeded the limit of 50 allocations"),
+        "{out}"
+    );
+
+    assert_eq!(run_with("print(1 + 1);", steps), "2");
+
+    // both backends take the same steps, and only count what is still alive
+    let src = "fn f(n) { return [n]; } let i = 0; \
+        while i < 100000 { let p = f(i); i += p[0] - i + 1; } print(i);";
+    let limits = Limits {
+        steps: Some(200_000),
+        depth: Some(200),
+        size: Some(1 << 20),
+        allocations: Some(100),
+    };
+
+    for backend in [Backend::Tree, Backend::Vm] {
+        let tokens = super::lex(src).0.unwrap();
+        let exprs = super::parse(src, &tokens).0.unwrap();
+        let env = super::new_env();
+        env.borrow_mut().backend = backend;
+        env.borrow_mut().meter = Rc::new(Meter::new(limits));
+        assert_eq!(super::interp_in(src, &tokens, exprs, &env), "100000");
+
+        let steps = Limits {
+            steps: Some(199_999),
+            ..limits
+        };
+        let env = super::new_env();
+        env.borrow_mut().backend = backend;
+        env.borrow_mut().meter = Rc::new(Meter::new(steps));
+        let exprs = super::parse(src, &tokens).0.unwrap();
+        let out = super::interp_in(src, &tokens, exprs, &env);
+        assert!(out.contains("ran out of steps after 199999"), "{out}");
+    }
+}
+
+#[test]
+fn interrupt() {
+    let src = "let i = 0; while true { i += 1; }";
+    let tokens = super::lex(src).0.unwrap();
+    let exprs = super::parse(src, &tokens).0.unwrap();
+
+    let interrupt = super::interrupt::Interrupt::new();
+    let handle = interrupt.clone();
+    let canceller = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        handle.cancel();
+    });
+
+    let env = super::new_env();
+    env.borrow_mut().interrupt = interrupt;
+    let out = super::interp_in(src, &tokens, exprs.clone(), &env);
+    canceller.join().unwrap();
+    assert!(out.contains("execution cancelled"), "{out}");
+
+    let env = super::new_env();
+    env.borrow_mut().backend = super::vm::Backend::Vm;
+    env.borrow_mut().interrupt =
+        super::interrupt::Interrupt::with_timeout(std::time::Duration::from_millis(50));
+    let out = super::interp_in(src, &tokens, exprs, &env);
+    assert!(out.contains("execution cancelled"), "{out}");
+}
+
+#[test]
+fn tail_calls() {
+    let src = "fn count(n, acc) { if n == 0 { return acc; } return count(n - 1, acc + 1); }
+        fn even(n) { if n == 0 { return true; } return odd(n - 1); }
+        fn odd(n) { if n == 0 { return false; } return even(n - 1); }
+        print(count(20000, 0), even(5001));";
+    assert_eq!(run(src), "20000 false");
+    assert_eq!(run_vm(src), "20000 false");
+
+    let src = "fn deep(n) { return 1 + deep(n + 1); } deep(0);";
+    let out = run(src);
+    assert!(
+        out.contains("exceeded the maximum recursion depth of 1000"),
+        "{out}"
+    );
+    assert!(out.contains("deep(n + 1)"), "{out}");
+    assert_eq!(run_vm(src), out);
+}
+
+#[test]
+fn deep_nesting() {
+    let src = format!(
+        "let x = {}1{}; {} x += 1; {} print(x);",
+        "(".repeat(500),
+        ")".repeat(500),
+        "{".repeat(500),
+        "}".repeat(500)
+    );
+    assert_eq!(run(&src), "2");
+    assert_eq!(run_vm(&src), "2");
+
+    // refused before they are parsed, as even dropping a tree that deep would overflow the stack
+    for src in [
+        format!("print({}1{});", "(".repeat(6000), ")".repeat(6000)),
+        format!("print({}1);", "-".repeat(20_000)),
+        format!("print(1{});", "+1".repeat(20_000)),
+        format!("let a = [0]; print(a{});", "[0]".repeat(20_000)),
+        format!("if true {{}}{}", " else if true {}".repeat(20_000)),
+    ] {
+        let tokens = super::lex(&src).0.unwrap();
+        let (exprs, errors) = super::parse(&src, &tokens);
+        assert!(exprs.is_none());
+        assert!(
+            errors.contains("expression is nested more than 5000 levels deep"),
+            "{errors}"
+        );
+    }
+
+    // but not just long
+    let src = format!(
+        "let x = 0; {} print(x, [{}0]);",
+        "if true { x += 1; } fn f() {} "
+            .repeat(3000)
+            .replace("fn f() {}", ""),
+        "1, ".repeat(6000)
+    );
+    assert_eq!(run(&src).split(' ').next(), Some("3000"));
+}
+
+#[test]
+#[allow(clippy::literal_string_with_formatting_args)]
+fn cycles() {
+    let src = "fn counter() {
+            let self::count = 0;
+            self::inc() { self::count = count + 1; return count; }
+        }
+
+        fn make() {
+            let a = [0, 1];
+            a[0] = a;
+            let o = struct { let v = 1; fn f() { return o; } };
+            return counter()::inc();
+        }
+
+        let i = 0;
+        while i < 3000 { make(); i += 1; }
+
+        let before = heap();
+        let freed = gc();
+        print(before['collections'] > 0, before['freed'] + freed >= 3000 * 4, heap()['live'] < 10);";
+
+    assert_eq!(run(src), "true true true");
+    assert_eq!(run_vm(src), "true true true");
+
+    // the cycle between a fn and the scope it was declared in outlives the program, until freed
+    let src = "let a = [0]; a[0] = a; fn f() { return a; }";
+    let tokens = super::lex(src).0.unwrap();
+    let exprs = super::parse(src, &tokens).0.unwrap();
+
+    let env = super::new_env();
+    let _ = super::interp_in(src, &tokens, exprs, &env);
+    let a = Rc::downgrade(&env.borrow().get("a").unwrap());
+
+    assert!(super::gc::free(env) >= 2);
+    assert_eq!(a.strong_count(), 0);
+
+    // a value that contains itself can still be compared, copied and printed
+    for run in [run, run_vm] {
+        let src = "let a = [0]; a[0] = a;
+            let b = &a; b[0][0] = 1; // the copy contains itself, not `a`
+            print(a == a, a, b, '{a:?}', sort([a, a]));";
+        assert_eq!(run(src), "true [...] [1] [...] [[...], [...]]");
+    }
+}
+
+#[test]
+fn owned() {
+    // nothing parsed borrows its source, so it can be kept and sent to another thread
+    let (tokens, exprs) = {
+        let src = String::from("fn f(x) { return x * 2; } print(f(21));");
+        let tokens = super::lex(&src).0.unwrap();
+        let exprs = super::parse(&src, &tokens).0.unwrap();
+        (tokens, exprs)
+    };
+
+    let out = std::thread::spawn(move || super::interp("", &tokens, exprs));
+    assert_eq!(out.join().unwrap(), "42");
+
+    for backend in [super::vm::Backend::Tree, super::vm::Backend::Vm] {
+        let env = super::new_env();
+        env.borrow_mut().backend = backend;
+
+        let next = |src: &str| super::interp_next(src, &env);
+
+        assert_eq!(next("let x = 1; fn f(y) { return x + y; }"), "");
+        assert_eq!(next("x += 1; print(f(10));"), "12");
+        assert!(next("print(g);").contains("variable `g` does not exist"));
+        assert_eq!(next("fn g() { return f(x); } print(g());"), "4");
+        assert!(next("print(f(x) +);").contains("print(f(x) +);"));
+        assert_eq!(next("print(x);"), "2");
+    }
+
+    // a name is freed along with the last program that uses it
+    let tokens = super::lex("let some_name = 1;").0.unwrap();
+    let super::span::Span(super::lexer::Token::Ident(name), _) = &tokens[1] else {
+        panic!("{tokens:?}");
+    };
+    let name = std::sync::Arc::downgrade(name);
+    drop(tokens);
+    assert!(name.upgrade().is_none());
+}
+
+#[test]
+fn locks() {
+    for run in [run, run_vm] {
+        let out = run("let o = [1, 2]; lock o; o[0] = 5; print(o);");
+        assert!(
+            out.contains("cannot assign to `o` because it is locked"),
+            "{out}"
+        );
+
+        let out = run("let o = struct { let a = 1; }; lock o; o::a = 5;");
+        assert!(
+            out.contains("cannot assign to `o` because it is locked"),
+            "{out}"
+        );
+
+        // the value is locked too, so no other variable can change it
+        let src = "let a = struct { let k = 1; let v = [1]; }; let b = a; let v = a::v;
+            lock a; print(a::k, len(a::v));
+            fn poke(o) { o::k = 5; return o; }";
+        for (write, error) in [
+            (
+                "b::k = 5;",
+                "cannot assign to field `k` because it is locked",
+            ),
+            (
+                "b['k'] += 1;",
+                "cannot assign to field `k` because it is locked",
+            ),
+            (
+                "poke(b);",
+                "cannot assign to field `k` because it is locked",
+            ),
+            ("b::v[0] = 5;", "cannot assign into a locked array"),
+            ("v[0] = 5;", "cannot assign into a locked array"),
+        ] {
+            let out = run(&format!("{src} {write}"));
+            assert!(out.starts_with("1 1") && out.contains(error), "{out}");
+        }
+
+        let out = run("let o = struct { let a = 1; }; lock o::a; o::a += 1;");
+        assert!(
+            out.contains("cannot assign to field `a` because it is locked"),
+            "{out}"
+        );
+
+        let out = run("let o = [1]; lock o; o = [2];");
+        assert!(
+            out.contains("cannot assign to `o` because it is locked"),
+            "{out}"
+        );
+
+        // an env keeps its unlocked properties mutable
+        let src = "fn counter() { let self::count = 0; }
+            let c = counter(); lock c; c::count += 2; print(c::count);";
+        assert_eq!(run(src), "2");
+
+        let src = "let o = struct { let a = 1; }; let a = [[1], 2];
+            o::a += 5; o['a'] *= 2; a[0][0] -= 3; a[1] <<= 2;
+            let p = &o; lock o; p::a = 0; print(o::a, a, p::a);";
+        assert_eq!(run(src), "12 [[-2], 8] 0");
+
+        // a copy of an env has variables of its own
+        let src = "fn counter() { let self::count = 0; let self::items = [1]; }
+            let c = counter(); let d = &c; d::count = 5; d::items[0] = 2;
+            print(c::count, c::items, d::count, d::items);";
+        assert_eq!(run(src), "0 [1] 5 [2]");
+    }
+}
+
+#[test]
+fn patterns() {
+    let is = |src: &str| {
+        run(&format!(
+            "let o = struct {{ let a = 1; let b = 'x'; }}; print({src});"
+        ))
+    };
+
+    assert_eq!(
+        is("5?_, 5?5, 5?'5', 5?int, 'a'?str | bool, 5?0..5, 5?0..=5"),
+        "true true false true true false true"
+    );
+    assert_eq!(
+        is("o?struct { a: int, b }, o?struct { a: str }, o?struct { c }, o?object"),
+        "true false false true"
+    );
+    assert_eq!(
+        is("set { 1, 'a' }?set { str, int }, set { 1, 2 }?set { int, 1 }, set { 1 }?set { 1, 2 }"),
+        "true true false"
+    );
+    assert_eq!(
+        is("[1, 'a', [2]]?[int, str, [_]], [1, 2]?[int], [1]?array, (1)?[1]"),
+        "true false true false"
+    );
+
+    let src = "let x = 3;
+        let o = struct { let v = 3; fn is(self, other) { return self['v'] == other; } };
+        print(o?(x), o?4, 4?(x));";
+    assert_eq!(run(src), "true false false");
+
+    let out = run("let o = struct { fn is(self, other) { return 1; } }; print(o?1);");
+    assert!(
+        out.contains("fn `is` must return `bool`, not `int`"),
+        "{out}"
+    );
+}
+
+#[test]
+fn restrictions() {
+    for run in [run, run_vm] {
+        assert_eq!(run("print(5{it > 0}, 5{it > 9});"), "5 _");
+
+        let src = "fn positive(n) { return n > 0; } print(5{positive}, 0{positive});";
+        assert_eq!(run(src), "5 _");
+
+        let src = "let age = 0{it >= 0}; age = 5; age += 1; print(age);";
+        assert_eq!(run(src), "6");
+
+        let out = run("let age = 0{it >= 0}; age = 5; age = -1; print(age);");
+        assert!(
+            out.contains("value -1 does not satisfy the restriction on `age`"),
+            "{out}"
+        );
+
+        let out = run("let age = 0{it >= 0}; age -= 1;");
+        assert!(
+            out.contains("value -1 does not satisfy the restriction on `age`"),
+            "{out}"
+        );
+
+        let out = run("let age = (-1){it >= 0};");
+        assert!(
+            out.contains("value -1 does not satisfy the restriction on `age`"),
+            "{out}"
+        );
+
+        let out = run("print(5{it + 1});");
+        assert!(
+            out.contains("restriction must be of type `bool`, not `int`"),
+            "{out}"
+        );
+
+        // the restriction stays with the binding, also from inside a fn
+        let src = "let n = 1{it < 10}; fn bump() { n = n * 10; } bump();";
+        assert!(run(src).contains("value 10 does not satisfy the restriction on `n`"));
+    }
+}
+
+#[test]
+fn epsilon() {
+    for run in [run, run_vm] {
+        let src =
+            "let x = 5; let y = 3; print(x ==~2 y, x ==~1 y, x >~1 y, 1 >~1 2, y <=~(x - 4) 4);";
+        assert_eq!(run(src), "true false true false true");
+
+        // the epsilon is only a number or a group, so `2 y` is not `2 * y` here
+        let src = "let x = 5; let y = 3; print(x + 1 ==~2 y * 2, 1 ==~0 1 == true, 2 y);";
+        assert_eq!(run(src), "true true 6");
+
+        assert_eq!(
+            run("print(cmp(10, 11, 2), cmp(10, 13, 2), cmp(1, 2));"),
+            "==~ < <"
+        );
+
+        let out = run("print(1 ==~('a') 2);");
+        assert!(
+            out.contains("cannot compare `int` with `int` within `str`"),
+            "{out}"
+        );
+        let out = run("print(1 ==~(0 - 1) 2);");
+        assert!(
+            out.contains("epsilon cannot be negative, but is -1"),
+            "{out}"
+        );
+        let out = run("print(cmp(1));");
+        assert!(
+            out.contains("fn `cmp` expected 2 to 3 arguments but got 1"),
+            "{out}"
+        );
+    }
+}
+
+#[test]
+fn ordering() {
+    for run in [run, run_vm] {
+        let src = "print('a' < 'b', 'b' <= 'a', false < true, [1, 2] < [1, 3], [1] < [1, 0], [2] > [1, 9]);";
+        assert_eq!(run(src), "true false true true true true");
+
+        assert_eq!(
+            run("print(cmp('a', 'b'), cmp([1], [1]), cmp(true, false));"),
+            "< == >"
+        );
+
+        let src = "fn num(n) { return struct { let n = n; fn cmp(self, other) { return cmp(self['n'], other['n']); } }; }
+            print(num(1) < num(2), num(2) <= num(1), cmp(num(3), num(3)));";
+        assert_eq!(run(src), "true false ==");
+
+        let out = run("print(1 < 'a');");
+        assert!(out.contains("cannot test inequality `int < str`"), "{out}");
+
+        let out = run("print(cmp([1], ['a']));");
+        assert!(out.contains("cannot compare `int` with `str`"), "{out}");
+
+        let src = "let o = struct { fn cmp(self, other) { return 1; } }; print(o < o);";
+        let out = run(src);
+        assert!(
+            out.contains("fn `cmp` must return `ordering`, not `int`"),
+            "{out}"
+        );
+
+        // values that contain themselves compare without overflowing the stack
+        let src = "let a = [0]; a[0] = a; let b = [0]; b[0] = b;
+            print(a == a, a == b, a != b, a <= b, cmp(a, a), a == [a, 1]);";
+        assert_eq!(run(src), "true true false true == false");
+    }
+}
+
+#[test]
+fn modules() {
+    let root = std::env::temp_dir().join(format!("vea-modules-{}", std::process::id()));
+    let (first, second) = (root.join("first"), root.join("second"));
+    std::fs::create_dir_all(first.join("lib")).unwrap();
+    std::fs::create_dir_all(&second).unwrap();
+
+    let write = |path: &std::path::Path, src: &str| std::fs::write(path, src).unwrap();
+    write(
+        &first.join("lib").join("m.vea"),
+        "print('loading m'); let x = 1; fn f() { return x; }",
+    );
+    write(&first.join("n.vea"), "use lib::m; let y = m::x;");
+    write(&first.join("a.vea"), "use b; let v = 'a';");
+    write(&first.join("b.vea"), "use a; let v = 'b';");
+    write(&first.join("c.vea"), "let v = 'first';");
+    write(&second.join("c.vea"), "let v = 'second';");
+    write(&second.join("d.vea"), "let v = 'second';");
+
+    let run = |src: &str| {
+        let tokens = super::lex(src).0.unwrap();
+        let exprs = super::parse(src, &tokens).0.unwrap();
+
+        let env = super::new_env();
+        env.borrow().modules.borrow_mut().search_path = vec![first.clone(), second.clone()];
+        super::interp_in(src, &tokens, exprs, &env)
+    };
+
+    // the search path is tried in order
+    assert_eq!(run("use c; use d; print(c::v, d::v);"), "first second");
+
+    // a module is only run once, and every `use` of it gets the same object
+    let src = "use lib::m; m::x = 2; use n; print(' ', n::y, m::f());";
+    assert_eq!(run(src), "loading m  2 1");
+
+    let out = run("use a;");
+    assert!(out.contains("import cycle: a -> b -> a"), "{out}");
+
+    let out = run("use nope;");
+    assert!(out.contains("cannot find module `nope`"), "{out}");
+
+    // once loaded, a module is not looked for again
+    let src = "fn g() { use c; return c::v; } print(g());";
+    let tokens = super::lex(src).0.unwrap();
+    let exprs = super::parse(src, &tokens).0.unwrap();
+    let env = super::new_env();
+    env.borrow().modules.borrow_mut().search_path = vec![first.clone()];
+    assert_eq!(super::interp_in(src, &tokens, exprs, &env), "first");
+
+    std::fs::remove_dir_all(&root).unwrap();
+    assert_eq!(super::interp_next("print(g());", &env), "first");
+}
+
+#[test]
+fn natives() {
+    for run in [run, run_vm] {
+        let src = "print(len('héllo'), type_of(len), len, keys(struct { let b = 1; let a = 2; }));";
+        assert_eq!(run(src), "5 fn fn len(1) [a, b]");
+
+        // natives are values like any other fn, and can be shadowed
+        assert_eq!(run("let f = len; print(map([[1], [1, 2]], f));"), "[1, 2]");
+        assert_eq!(run("fn len(x) { return 0; } print(len('abc'));"), "0");
+
+        let out = run("print(len('a', 'b'));");
+        assert!(
+            out.contains("fn `len` expected 1 arguments but got 2"),
+            "{out}"
+        );
+
+        let out = run("print(cmp(1));");
+        assert!(
+            out.contains("fn `cmp` expected 2 to 3 arguments but got 1"),
+            "{out}"
+        );
+
+        // type errors point at the argument, not the call
+        let out = run("print(has(struct {}, 5));");
+        assert!(out.contains("test.vea:1:22"), "{out}");
+
+        let out = run("let x = 1; x();");
+        assert!(out.contains("value is not a function"), "{out}");
+
+        let out = run("assert(1 == 2, 'math');");
+        assert!(out.contains("assertion failed: math"), "{out}");
+        let out = run("assert(1);");
+        assert!(out.contains("expected `bool`, found `int`"), "{out}");
+    }
+
+    // the embedder can register its own
+    let src = "print(double(21));";
+    let tokens = super::lex(src).0.unwrap();
+    let exprs = super::parse(src, &tokens).0.unwrap();
+
+    let env = super::new_env();
+    super::prelude::native(
+        &env.borrow(),
+        "double",
+        super::literal::Arity::exactly(1),
+        |_, args, _| {
+            let n = match &*args[0].0.borrow() {
+                super::literal::Literal::Integer(n) => n * 2,
+                _ => 0,
+            };
+            Ok(Rc::new(RefCell::new(super::literal::Literal::Integer(n))))
+        },
+    );
+    assert_eq!(super::interp_in(src, &tokens, exprs, &env), "42");
+}
+
+#[test]
+fn strings() {
+    let s = |src: &str| run(&format!("use std::string; print({src});"));
+
+    assert_eq!(s("string::len('héllo'), string::byte_len('héllo')"), "5 6");
+    assert_eq!(
+        s("string::chars('ab'), string::split('a,b,,c', ',')"),
+        "[a, b] [a, b, , c]"
+    );
+    assert_eq!(
+        s("string::join(['a', 'b', 'c'], '-'), string::join([], '-') == ''"),
+        "a-b-c true"
+    );
+    assert_eq!(
+        s("string::trim('  a b  '), string::upper('aé'), string::lower('AÉ')"),
+        "a b AÉ aé"
+    );
+    assert_eq!(
+        s("string::replace('a-b-c', '-', '+'), string::repeat('ab', 3)"),
+        "a+b+c ababab"
+    );
+    assert_eq!(
+        s("string::contains('hello', 'll'), string::starts_with('hello', 'he'), string::ends_with('hello', 'he')"),
+        "true true false"
+    );
+    assert_eq!(
+        s("string::find('héllo', 'l'), string::find('hello', 'z')?int"),
+        "2 false"
+    );
+    assert_eq!(s("string::parse_int(' -42 ') + 1"), "-41");
+    assert_eq!(
+        s("'héllo'[1], 'héllo'[1..3], 'héllo'[..=1], 'héllo'[3..], [1, 2, 3][1..]"),
+        "é él hé lo [2, 3]"
+    );
+
+    // errors point at the argument at fault
+    let out = s("string::len(5)");
+    assert!(
+        out.contains("expected `str`, found `int`") && out.contains("test.vea:1:36"),
+        "{out}"
+    );
+    let out = s("string::split('abc', '')");
+    assert!(
+        out.contains("cannot split on an empty string, use `chars` instead"),
+        "{out}"
+    );
+    let out = s("string::join(['a', 1], '')");
+    assert!(
+        out.contains("expected an array of `str`, found `int` at index 1"),
+        "{out}"
+    );
+    let out = s("string::replace('abc', '', 'x')");
+    assert!(out.contains("cannot replace an empty string"), "{out}");
+    let out = s("string::repeat('ab', -1)");
+    assert!(out.contains("cannot repeat a string -1 times"), "{out}");
+    let out = s("string::repeat('ab', 4611686018427387904)");
+    assert!(
+        out.contains("with a size of 9223372036854775808 exceeds the maximum size of 1073741824"),
+        "{out}"
+    );
+    let out = s("string::repeat('abc', 9223372036854775807)");
+    assert!(
+        out.contains("cannot repeat a string 9223372036854775807 times"),
+        "{out}"
+    );
+    let out = s("string::parse_int('4x')");
+    assert!(out.contains("cannot parse '4x' as an `int`"), "{out}");
+    let out = s("'abc'[5]");
+    assert!(
+        out.contains("index 5 is out of bounds for an array of length 3"),
+        "{out}"
+    );
+
+    // and none of them can make a string bigger than the limits allow
+    let run_with = |src: &str, size| {
+        let src = format!("use std::string; {src}");
+        let tokens = super::lex(&src).0.unwrap();
+        let exprs = super::parse(&src, &tokens).0.unwrap();
+        let limits = super::limits::Limits {
+            size: Some(size),
+            ..super::limits::Limits::default()
+        };
+        super::interp_with_limits(&src, &tokens, exprs, limits)
+    };
+
+    let out = run_with("string::repeat('ab', 51);", 100);
+    assert!(
+        out.contains("with a size of 102 exceeds the maximum size of 100"),
+        "{out}"
+    );
+    let out = run_with("string::repeat('ab', 9223372036854775807);", 100);
+    assert!(
+        out.contains("with a size of 18446744073709551614 exceeds"),
+        "{out}"
+    );
+    let out = run_with(
+        "let s = string::repeat('a', 50); string::replace(s, 'a', 'bcd');",
+        100,
+    );
+    assert!(
+        out.contains("with a size of 150 exceeds the maximum size of 100"),
+        "{out}"
+    );
+    let out = run_with(
+        "let s = string::repeat('a', 60); string::join([s, s], '');",
+        100,
+    );
+    assert!(
+        out.contains("with a size of 120 exceeds the maximum size of 100"),
+        "{out}"
+    );
+    assert_eq!(
+        run_with(
+            "print(string::repeat('ab', 50) == string::repeat('ba', 50));",
+            100
+        ),
+        "false"
+    );
+
+    // checked before they are made, or these would take about a terabyte
+    let big = "let s = string::repeat('a', 1000000);";
+    let out = run_with(&format!("{big} string::replace(s, 'a', s);"), 1_000_000);
+    assert!(
+        out.contains("with a size of 1000000000000 exceeds"),
+        "{out}"
+    );
+    let out = run_with(&format!("{big} string::join([s, s], s);"), 1_000_000);
+    assert!(out.contains("with a size of 3000000 exceeds"), "{out}");
+}
+
+#[test]
+fn math() {
+    let m = |src: &str| run(&format!("use std::math; print({src});"));
+
+    assert_eq!(
+        m("math::abs(-3), math::sign(-3), math::min(3, 1, 2), math::max(3, 1, 2)"),
+        "3 -1 1 3"
+    );
+    assert_eq!(
+        m("math::pow(-2, 3), math::sqrt(49), math::isqrt(50), math::gcd(-12, 18), math::lcm(4, 6)"),
+        "-8 7 7 6 12"
+    );
+    assert_eq!(
+        m("math::factorial(20), math::binomial(10, 3), math::binomial(3, 10)"),
+        "2432902008176640000 120 0"
+    );
+    assert_eq!(
+        m("math::mod_pow(-2, 3, 5), math::mod_pow(3, 0, 1), math::mod_pow(2, 9223372036854775807, 9223372036854775783)"),
+        "2 0 33554432"
+    );
+
+    // large primes are quick, and carmichael numbers and strong pseudoprimes aren't fooling
+    assert_eq!(
+        m("math::is_prime(9223372036854775783), math::is_prime(2), math::is_prime(1), math::is_prime(-7)"),
+        "true true false false"
+    );
+    assert_eq!(
+        m("math::is_prime(561), math::is_prime(3215031751), math::is_prime(3825123056546413051)"),
+        "false false false"
+    );
+
+    assert_eq!(
+        m("math::pi(0), math::pi(4), math::e(2), math::pi(18)"),
+        "3 31415 271 3141592653589793238"
+    );
+    let out = m("math::pi(19)");
+    assert!(
+        out.contains("expected at most 18 decimal places, found 19"),
+        "{out}"
+    );
+
+    for src in [
+        "math::abs(-9223372036854775807 - 1)",
+        "math::pow(2, 63)",
+        "math::factorial(21)",
+        "math::lcm(9223372036854775807, 9223372036854775806)",
+    ] {
+        let out = m(src);
+        assert!(out.contains("result does not fit in an `int`"), "{out}");
+    }
+
+    let out = m("math::sqrt(50)");
+    assert!(out.contains("50 is not a perfect square"), "{out}");
+    let out = m("math::isqrt(-1)");
+    assert!(
+        out.contains("expected a non-negative `int`, found -1"),
+        "{out}"
+    );
+    let out = m("math::mod_pow(2, 3, 0)");
+    assert!(
+        out.contains("expected a positive modulus, found 0"),
+        "{out}"
+    );
+}
@@ -0,0 +1,379 @@
+//! A stack machine that runs the bytecode of [`compiler`](crate::compiler). It gives the same
+//! results and errors as the tree-walking interpreter, only faster.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::Expr,
+    common::Tag,
+    compiler::{compile, Chunk, CmpOp, Op},
+    env::Env,
+    format::Spec,
+    interpreter::{
+        call, check_call, compare, index_into, indexable, interp, method, none, print, set,
+        TailCall,
+    },
+    literal::{Literal, TotalOrdering},
+    rc_cell,
+    span::{RawSpan, Span},
+};
+
+/// How a program is run.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Backend {
+    /// Walks the syntax tree.
+    #[default]
+    Tree,
+    /// Compiles each list of statements to bytecode once, and runs that.
+    Vm,
+}
+
+/// Compiled statement lists, by the range and number of their statements.
+pub type Chunks<'a> = Rc<RefCell<HashMap<(usize, usize, usize), Rc<Chunk<'a>>>>>;
+
+type Value<'a> = Rc<RefCell<Literal<'a>>>;
+
+/// Runs a list of statements in `env`, like [`exec`](crate::interpreter::exec) does.
+pub fn exec<'a>(many: &[Span<Expr>], env: &Rc<RefCell<Env<'a>>>) -> Result<Env<'a>, Span<String>> {
+    let chunk = chunk(many, &env.borrow());
+    run(&chunk, env)?;
+
+    Ok(env.borrow().clone())
+}
+
+fn chunk<'a>(many: &[Span<Expr>], env: &Env<'a>) -> Rc<Chunk<'a>> {
+    let key = (
+        many.first().map_or(0, |x| x.1.start),
+        many.last().map_or(0, |x| x.1.end),
+        many.len(),
+    );
+
+    env.chunks
+        .borrow_mut()
+        .entry(key)
+        .or_insert_with(|| Rc::new(compile(many, &env.resolution.borrow())))
+        .clone()
+}
+
+fn pop<'a>(stack: &mut Vec<Value<'a>>) -> Value<'a> {
+    stack.pop().expect("bytecode left the stack empty")
+}
+
+fn run<'a>(chunk: &Chunk<'a>, root: &Rc<RefCell<Env<'a>>>) -> Result<(), Span<String>> {
+    let mut stack: Vec<Value<'a>> = vec![];
+    let mut scopes = vec![root.clone()];
+    let mut pc = 0;
+    let meter = root.borrow().meter.clone();
+    let heap = root.borrow().heap.clone();
+
+    while let Some(op) = chunk.ops.get(pc) {
+        let span = chunk.spans[pc];
+        pc += 1;
+        let cur = scopes.last().unwrap().clone();
+
+        match op {
+            Op::Const(value) => stack.push(rc_cell!(value.clone())),
+            Op::Load(ident, at) => {
+                let value = cur.borrow().load(*at, &ident.0);
+                stack.push(value.map_err(|x| x.t(ident.1))?);
+            }
+            Op::Snapshot => {
+                let value = pop(&mut stack);
+                stack.push(rc_cell!(value.borrow().clone()));
+            }
+            Op::Pop => {
+                pop(&mut stack);
+            }
+
+            Op::Fresh(ident) => {
+                if cur.borrow().has(&ident.0) {
+                    return Err(format!("variable `{}` already exists", ident.0).t(ident.1));
+                }
+            }
+            Op::Exists(ident, at) => {
+                if !cur.borrow().exists(*at).map_err(|x| x.t(ident.1))? {
+                    return Err(format!("variable `{}` does not exist", ident.0).t(ident.1));
+                }
+            }
+            Op::Let(name, at, span) => {
+                let value = pop(&mut stack);
+                cur.borrow_mut()
+                    .assign(name, *at, value)
+                    .map_err(|x| x.t(*span))?;
+            }
+            Op::Set(ident, at, span) => {
+                let value = pop(&mut stack);
+                set(&mut cur.borrow_mut(), ident, *at, value, *span)?;
+            }
+
+            Op::Binary(op, span) => {
+                let (rhs, lhs) = (pop(&mut stack), pop(&mut stack));
+                let out = op.apply(lhs.borrow().clone(), rhs.borrow().clone());
+                stack.push(rc_cell!(out.map_err(|x| x.t(*span))?));
+            }
+            Op::Compare(op, span) => {
+                let (rhs, lhs) = (pop(&mut stack), pop(&mut stack));
+                stack.push(rc_cell!(cmp(
+                    &mut cur.borrow_mut(),
+                    *op,
+                    &lhs,
+                    &rhs,
+                    *span
+                )?));
+            }
+            Op::Eq(ne, span) => {
+                let (rhs, lhs) = (pop(&mut stack), pop(&mut stack));
+                let (lhs, rhs) = (lhs.borrow().clone(), rhs.borrow().clone());
+                let out = if *ne { lhs.rne(rhs) } else { lhs.req(rhs) };
+                stack.push(rc_cell!(out.map_err(|x| x.t(*span))?));
+            }
+            Op::Neg(span) => {
+                let value = pop(&mut stack).borrow().clone();
+                stack.push(rc_cell!((-value).map_err(|x| x.t(*span))?));
+            }
+            Op::Not(span) => {
+                let value = pop(&mut stack).borrow().clone();
+                stack.push(rc_cell!((!value).map_err(|x| x.t(*span))?));
+            }
+            Op::Copy => {
+                let value = pop(&mut stack);
+                stack.push(Literal::deep_copy(&value, &heap));
+            }
+            Op::Array(n) => {
+                let values = stack.split_off(stack.len() - n);
+                stack.push(rc_cell!(Literal::Array(values, false)));
+            }
+
+            Op::Indexable(span) => {
+                let parent = stack.last().unwrap().borrow();
+
+                if !indexable(&parent) {
+                    return Err(format!(
+                        "cannot index into a value with type `{}`",
+                        parent.type_of()
+                    )
+                    .t(*span));
+                }
+            }
+            Op::Index(span) => {
+                let (key, parent) = (pop(&mut stack), pop(&mut stack));
+                let key = key.borrow().clone();
+                stack.push(index_into(&parent.borrow(), key, *span)?);
+            }
+
+            Op::Callee { args, access, span } => {
+                check_call(&stack.last().unwrap().borrow(), args, *access, *span)?;
+            }
+            Op::Call { args, span } => {
+                let values = stack.split_off(stack.len() - args.len());
+                let f = pop(&mut stack).borrow().clone();
+
+                let out = if let Literal::NativeFn(_, _, native) = &f {
+                    let values = values
+                        .into_iter()
+                        .zip(args)
+                        .map(|(x, s)| Span(x, *s))
+                        .collect::<Vec<_>>();
+
+                    (native.0)(&mut cur.borrow_mut(), &values, *span)?
+                } else {
+                    call(&mut cur.borrow_mut(), &f, values, *span)?
+                };
+
+                stack.push(out);
+            }
+
+            Op::Print(n, newline, span) => {
+                let values = stack.split_off(stack.len() - n);
+                let mut text = values
+                    .iter()
+                    .map(|x| x.borrow().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                if *newline {
+                    text += "\n";
+                }
+
+                print(&cur.borrow(), &text, *span)?;
+            }
+            Op::Format(spec, span) => {
+                let value = pop(&mut stack);
+                let spec = match spec {
+                    Some(x) => Spec::parse(x).map_err(|x| x.t(*span))?,
+                    None => Spec::default(),
+                };
+
+                let text = spec.format(&value.borrow(), &cur.borrow().meter, *span)?;
+                stack.push(rc_cell!(Literal::String(text)));
+            }
+            Op::Concat(texts) => {
+                let values = stack.split_off(stack.len() - (texts.len() - 1));
+                let mut out = texts[0].clone();
+
+                for (value, text) in values.iter().zip(&texts[1..]) {
+                    out += &value.borrow().to_string();
+                    out += text;
+                }
+
+                stack.push(rc_cell!(Literal::String(out)));
+            }
+
+            Op::Jump(to) => {
+                // the end of a loop
+                if *to < pc {
+                    cur.borrow().interrupt.check(span)?;
+                    meter.step(span)?;
+                }
+
+                pc = *to;
+            }
+            Op::JumpUnless(to, span, message) => match *pop(&mut stack).borrow() {
+                Literal::Bool(true) => {}
+                Literal::Bool(false) => pc = *to,
+                _ => return Err((*message).to_string().t(*span)),
+            },
+
+            Op::Enter(is_unsafe) => {
+                let name = cur.borrow().name.clone();
+                let parent = Rc::new(RefCell::new(cur.borrow().clone()));
+                let mut local = Env::with_parent(name, parent);
+                local.is_unsafe |= is_unsafe;
+
+                scopes.push(Rc::new(RefCell::new(local)));
+            }
+            Op::Leave => {
+                scopes.pop();
+            }
+
+            Op::Return { span, next, call } => {
+                let tail = call.as_ref().map(|(span, args)| {
+                    let values = stack.split_off(stack.len() - args.len());
+                    (*span, args, pop(&mut stack).borrow().clone(), values)
+                });
+                let value = if tail.is_some() {
+                    none()
+                } else {
+                    pop(&mut stack)
+                };
+
+                let Some(name) = cur.borrow().name.clone() else {
+                    return Err("used `return` statement outside of a `fn` block"
+                        .to_string()
+                        .t(*span));
+                };
+
+                if let Some(next) = next {
+                    return Err("statement after `return` is never reached"
+                        .to_string()
+                        .t(*next));
+                }
+
+                let value = match tail {
+                    Some((span, args, f, values)) => tail_call(&cur, f, values, args, span)?,
+                    None => value,
+                };
+
+                let mut root = root.borrow_mut();
+                root.set_ret(&name, value);
+                root.retyet = true;
+
+                return Ok(());
+            }
+
+            Op::Eval(expr) => {
+                let value = interp(&mut cur.borrow_mut(), (**expr).clone())?;
+                stack.push(value);
+
+                // a `return` inside of it, e.g. in a nested block the tree ran
+                let cur = cur.borrow();
+
+                if cur.retyet {
+                    if scopes.len() > 1 {
+                        let mut root = root.borrow_mut();
+
+                        if let Some(name) = &cur.name {
+                            if let Some(value) = cur.retval.borrow().get(name) {
+                                root.set_ret(name, value.clone());
+                            }
+                        }
+
+                        root.retyet = true;
+                    }
+
+                    return Ok(());
+                }
+            }
+        }
+
+        if op.makes_value() {
+            meter.value(stack.last().unwrap(), span)?;
+            heap.track(stack.last().unwrap());
+            meter.live(&heap, span)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Makes the call of a `return f(...)`, or leaves it to the caller of this fn if it can. That is,
+/// unless `f` is native, or this is not the body of a fn but e.g. a test.
+fn tail_call<'a>(
+    cur: &Rc<RefCell<Env<'a>>>,
+    f: Literal<'a>,
+    values: Vec<Value<'a>>,
+    args: &[RawSpan],
+    span: RawSpan,
+) -> Result<Value<'a>, Span<String>> {
+    let mut cur = cur.borrow_mut();
+
+    if let Literal::NativeFn(_, _, native) = &f {
+        let values = values.into_iter().zip(args).map(|(x, s)| Span(x, *s));
+        return (native.0)(&mut cur, &values.collect::<Vec<_>>(), span);
+    }
+
+    let Some(tail) = &cur.tail else {
+        return call(&mut cur, &f, values, span);
+    };
+
+    *tail.borrow_mut() = Some(TailCall {
+        f,
+        args: values,
+        span,
+        is_unsafe: cur.is_unsafe,
+    });
+
+    Ok(none())
+}
+
+/// `<`, `<=`, `>` and `>=`, which go through `fn cmp(self, other)` if `lhs` has one.
+fn cmp<'a>(
+    program: &mut Env<'a>,
+    op: CmpOp,
+    lhs: &Value<'a>,
+    rhs: &Value<'a>,
+    span: RawSpan,
+) -> Result<Literal<'a>, Span<String>> {
+    use TotalOrdering::{Equal, GreaterThan, LessThan};
+
+    if method(lhs, "cmp").is_some() {
+        let o = compare(program, lhs, rhs, span)?;
+
+        return Ok(Literal::Bool(match op {
+            CmpOp::Gt => matches!(o, GreaterThan),
+            CmpOp::Ge => matches!(o, GreaterThan | Equal),
+            CmpOp::Lt => matches!(o, LessThan),
+            CmpOp::Le => matches!(o, LessThan | Equal),
+        }));
+    }
+
+    let (lhs, rhs) = (lhs.borrow(), rhs.borrow());
+
+    match op {
+        CmpOp::Gt => lhs.rgt(&rhs),
+        CmpOp::Ge => lhs.rge(&rhs),
+        CmpOp::Lt => lhs.rlt(&rhs),
+        CmpOp::Le => lhs.rle(&rhs),
+    }
+    .map_err(|x| x.t(span))
+}